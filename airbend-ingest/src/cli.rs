@@ -1,16 +1,21 @@
-use clap::Parser;
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
 
 #[derive(Parser)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, subcommand_negates_reqs = true)]
 /// Welcome to the airbend-ingest tool. Query the London Air Quality Network API (LAQN) and ingest into a databend database.
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Start date to request data from. Ensure it is format 'yyyy-mm-dd'.
-    #[arg(short, long)]
-    pub start_date: String,
+    #[arg(short, long, required = true)]
+    pub start_date: Option<String>,
 
     /// End date to request data from. Ensure it is format 'yyyy-mm-dd'.
-    #[arg(short, long)]
-    pub end_date: String,
+    #[arg(short, long, required = true)]
+    pub end_date: Option<String>,
 
     /// Maximum number of concurrent connections
     #[arg(short, long, default_value_t = 5)]
@@ -19,4 +24,47 @@ pub struct Cli {
     /// Databend connection string
     #[arg(short, long)]
     pub connection_string: Option<String>,
+
+    /// Database to create tables in and ingest into, instead of the connection's default database.
+    /// Created if it does not exist.
+    #[arg(long)]
+    pub database: Option<String>,
+
+    /// Prefix for table names, e.g. 'dev_', so several environments can share one database.
+    #[arg(long, default_value = "")]
+    pub table_prefix: String,
+
+    /// Allow table migrations that drop columns or change column types.
+    #[arg(long, default_value_t = false)]
+    pub allow_destructive_migrations: bool,
+
+    /// Load sensor readings through a databend stage instead of INSERT statements, for large backfills.
+    #[arg(long, default_value_t = false)]
+    pub bulk_load: bool,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Write a dbt sources.yml describing the raw tables this tool ingests into, placed by
+    /// --database and --table-prefix.
+    DbtSources {
+        /// File to write. Prints to stdout if not given.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Name of the dbt source.
+        #[arg(long, default_value = "laqn")]
+        source: String,
+
+        /// Database schema the tables live in, unless --database is given.
+        #[arg(long, default_value = "default")]
+        schema: String,
+    },
+    /// Print the CREATE DATABASE and CREATE TABLE statements for the tables this tool ingests
+    /// into, placed by --database and --table-prefix, for review.
+    Ddl {
+        /// File to write. Prints to stdout if not given.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
 }
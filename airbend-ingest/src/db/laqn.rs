@@ -1,41 +1,265 @@
+use std::borrow::Cow;
+
 use airbend_table::AirbendTable;
 
+// Strings are Cows, so rows can borrow from the API response when written and own their
+// values when read back.
+
+/// Sensor readings as scraped from the LAQN API, one row per site, species and measurement time.
 #[derive(AirbendTable)]
-#[airbend_table(table_name = "raw_sensor_reading")]
-pub struct FlatSensorReading {
+#[airbend_table(
+    table_name = "raw_sensor_reading",
+    cluster_by = ["site_code", "measurement_date"]
+)]
+pub struct FlatSensorReading<'a> {
+    /// When the reading was fetched from the LAQN API
     #[airbend_col(dtype = "TIMESTAMP")]
     pub scrape_time: jiff::Timestamp,
-    #[airbend_col(dtype = "VARCHAR")]
-    pub site_code: String,
-    #[airbend_col(dtype = "VARCHAR")]
+    /// A unique code for the LAQN sensor
+    #[airbend_col(dtype = "VARCHAR", key)]
+    pub site_code: Cow<'a, str>,
+    /// The time of the sensor reading, as reported by the API
+    #[airbend_col(dtype = "VARCHAR", key)]
     pub measurement_date: String,
-    #[airbend_col(dtype = "VARCHAR")]
-    pub species_code: Option<String>,
+    /// The species of the pollutant, empty if the API gave none
+    #[airbend_col(dtype = "VARCHAR", key)]
+    pub species_code: String,
+    /// The sensor reading, as reported by the API
     #[airbend_col(dtype = "VARCHAR")]
     pub value: Option<String>,
 }
 
+/// Metadata for every LAQN site, as scraped from the LAQN API. Each scrape adds a row per site.
 #[derive(AirbendTable)]
 #[airbend_table(table_name = "raw_metadata")]
-pub struct SiteMeta {
+pub struct SiteMeta<'a> {
+    /// When the metadata was fetched from the LAQN API
     #[airbend_col(dtype = "TIMESTAMP")]
     pub scrape_time: jiff::Timestamp,
+    /// A unique code for the LAQN sensor
     #[airbend_col(dtype = "VARCHAR")]
-    pub site_code: String,
+    pub site_code: Cow<'a, str>,
+    /// The name of the LAQN sensor
     #[airbend_col(dtype = "VARCHAR")]
-    pub site_name: String,
+    pub site_name: Cow<'a, str>,
+    /// The type of LAQN sensor
     #[airbend_col(dtype = "VARCHAR")]
-    pub site_type: String,
+    pub site_type: Cow<'a, str>,
+    /// The date the LAQN sensor closed
     #[airbend_col(dtype = "TIMESTAMP")]
-    pub date_closed: Option<String>,
+    pub date_closed: Option<jiff::Timestamp>,
+    /// The date the LAQN sensor opened
     #[airbend_col(dtype = "TIMESTAMP")]
-    pub date_opened: Option<String>,
+    pub date_opened: Option<jiff::Timestamp>,
+    /// Latitude of the sensor
     #[airbend_col(dtype = "VARCHAR")]
-    pub latitude: Option<String>,
+    pub latitude: Option<Cow<'a, str>>,
+    /// Longitude of the sensor
     #[airbend_col(dtype = "VARCHAR")]
-    pub longitude: Option<String>,
+    pub longitude: Option<Cow<'a, str>>,
+    /// The organisation that owns the sensor data
     #[airbend_col(dtype = "VARCHAR")]
-    pub data_owner: String,
+    pub data_owner: Cow<'a, str>,
+    /// URL for LAQN sensor information
     #[airbend_col(dtype = "VARCHAR")]
-    pub site_link: String,
+    pub site_link: Cow<'a, str>,
+}
+
+#[cfg(test)]
+mod tests {
+    use airbend_table::mock::MockConnection;
+    use airbend_table::{
+        create, insert, registry, select, upsert, Databend, LoadFormat, Namespace, Sqlite, TableDef,
+    };
+
+    use super::*;
+
+    fn readings(value: &str) -> Vec<FlatSensorReading<'static>> {
+        let scrape_time = jiff::Timestamp::from_second(1_700_000_000).unwrap();
+        ["NO2", "PM10", ""]
+            .into_iter()
+            .map(|species_code| FlatSensorReading {
+                scrape_time,
+                site_code: "MY1".into(),
+                measurement_date: "2024-01-01 00:00:00".to_string(),
+                species_code: species_code.to_string(),
+                value: Some(value.to_string()),
+            })
+            .collect()
+    }
+
+    fn site_meta() -> SiteMeta<'static> {
+        SiteMeta {
+            scrape_time: jiff::Timestamp::from_second(1_700_000_000).unwrap(),
+            site_code: "MY1".into(),
+            site_name: "Westminster - Marylebone Road".into(),
+            site_type: "Kerbside".into(),
+            date_closed: None,
+            date_opened: Some(jiff::Timestamp::from_second(946_684_800).unwrap()),
+            latitude: Some("51.52254".into()),
+            longitude: None,
+            data_owner: "City of Westminster".into(),
+            site_link: "https://www.londonair.org.uk".into(),
+        }
+    }
+
+    #[test]
+    fn dbt_sources_are_current() {
+        let yml = airbend_table::dbt::sources_yml(
+            "laqn",
+            "default",
+            &Namespace::new(),
+            &registry::tables(),
+        )
+        .unwrap();
+        assert_eq!(
+            yml,
+            include_str!("../../../airbend_pipeline/models/laqn_sensors/sources.yml"),
+            "regenerate sources.yml with airbend-ingest dbt-sources"
+        );
+    }
+
+    #[test]
+    fn flat_sensor_reading_sql() {
+        let namespace = Namespace::default();
+        let table = TableDef::of::<FlatSensorReading>();
+        let rows = &readings("1.5")[..1];
+
+        assert_eq!(
+            table.create_sql(&Databend, &namespace),
+            "CREATE TABLE IF NOT EXISTS `raw_sensor_reading` (`scrape_time` TIMESTAMP NOT NULL COMMENT 'When the reading was fetched from the LAQN API', \
+             `site_code` VARCHAR NOT NULL COMMENT 'A unique code for the LAQN sensor', \
+             `measurement_date` VARCHAR NOT NULL COMMENT 'The time of the sensor reading, as reported by the API', \
+             `species_code` VARCHAR NOT NULL COMMENT 'The species of the pollutant, empty if the API gave none', \
+             `value` VARCHAR NULL COMMENT 'The sensor reading, as reported by the API') CLUSTER BY (`site_code`, \
+             `measurement_date`) COMMENT = 'Sensor readings as scraped from the LAQN API, one row per site, species and measurement time.';"
+        );
+        assert_eq!(
+            insert().values(rows).sql(&Databend, &namespace).unwrap(),
+            "INSERT INTO `raw_sensor_reading` (`scrape_time`, `site_code`, `measurement_date`, \
+             `species_code`, `value`) VALUES ('2023-11-14 22:13:20', 'MY1', \
+             '2024-01-01 00:00:00', 'NO2', '1.5')"
+        );
+        assert_eq!(
+            upsert()
+                .values(rows)
+                .unwrap()
+                .sql(&Databend, &namespace)
+                .unwrap(),
+            "REPLACE INTO `raw_sensor_reading` (`scrape_time`, `site_code`, `measurement_date`, \
+             `species_code`, `value`) ON (`site_code`, `measurement_date`, \
+             `species_code`) VALUES ('2023-11-14 22:13:20', 'MY1', '2024-01-01 00:00:00', \
+             'NO2', '1.5')"
+        );
+        assert_eq!(
+            table.create_sql(&Sqlite, &namespace),
+            "CREATE TABLE IF NOT EXISTS \"raw_sensor_reading\" (\"scrape_time\" TIMESTAMP NOT NULL, \
+             \"site_code\" TEXT NOT NULL, \"measurement_date\" TEXT NOT NULL, \
+             \"species_code\" TEXT NOT NULL, \"value\" TEXT NULL, UNIQUE (\"site_code\", \
+             \"measurement_date\", \"species_code\"));"
+        );
+        assert_eq!(
+            insert().values(rows).sql(&Sqlite, &namespace).unwrap(),
+            "INSERT INTO \"raw_sensor_reading\" (\"scrape_time\", \"site_code\", \"measurement_date\", \
+             \"species_code\", \"value\") VALUES ('2023-11-14 22:13:20.000000', 'MY1', \
+             '2024-01-01 00:00:00', 'NO2', '1.5')"
+        );
+        assert_eq!(
+            upsert().values(rows).unwrap().sql(&Sqlite, &namespace).unwrap(),
+            "INSERT OR REPLACE INTO \"raw_sensor_reading\" (\"scrape_time\", \"site_code\", \
+             \"measurement_date\", \"species_code\", \"value\") VALUES ('2023-11-14 22:13:20.000000', \
+             'MY1', '2024-01-01 00:00:00', 'NO2', '1.5')"
+        );
+    }
+
+    #[test]
+    fn site_meta_sql() {
+        let namespace = Namespace::default();
+        let table = TableDef::of::<SiteMeta>();
+        let rows = [site_meta()];
+
+        assert_eq!(
+            table.create_sql(&Databend, &namespace),
+            "CREATE TABLE IF NOT EXISTS `raw_metadata` (`scrape_time` TIMESTAMP NOT NULL COMMENT 'When the metadata was fetched from the LAQN API', \
+             `site_code` VARCHAR NOT NULL COMMENT 'A unique code for the LAQN sensor', \
+             `site_name` VARCHAR NOT NULL COMMENT 'The name of the LAQN sensor', \
+             `site_type` VARCHAR NOT NULL COMMENT 'The type of LAQN sensor', \
+             `date_closed` TIMESTAMP NULL COMMENT 'The date the LAQN sensor closed', \
+             `date_opened` TIMESTAMP NULL COMMENT 'The date the LAQN sensor opened', \
+             `latitude` VARCHAR NULL COMMENT 'Latitude of the sensor', \
+             `longitude` VARCHAR NULL COMMENT 'Longitude of the sensor', \
+             `data_owner` VARCHAR NOT NULL COMMENT 'The organisation that owns the sensor data', \
+             `site_link` VARCHAR NOT NULL COMMENT 'URL for LAQN sensor information') COMMENT = 'Metadata for every LAQN site, as scraped from the LAQN API. Each scrape adds a row per site.';"
+        );
+        assert_eq!(
+            insert().values(&rows).sql(&Databend, &namespace).unwrap(),
+            "INSERT INTO `raw_metadata` (`scrape_time`, `site_code`, `site_name`, `site_type`, \
+             `date_closed`, `date_opened`, `latitude`, `longitude`, `data_owner`, \
+             `site_link`) VALUES ('2023-11-14 22:13:20', 'MY1', \
+             'Westminster - Marylebone Road', 'Kerbside', NULL, '2000-01-01 00:00:00', \
+             '51.52254', NULL, 'City of Westminster', 'https://www.londonair.org.uk')"
+        );
+        assert_eq!(
+            table.create_sql(&Sqlite, &namespace),
+            "CREATE TABLE IF NOT EXISTS \"raw_metadata\" (\"scrape_time\" TIMESTAMP NOT NULL, \
+             \"site_code\" TEXT NOT NULL, \"site_name\" TEXT NOT NULL, \"site_type\" TEXT NOT NULL, \
+             \"date_closed\" TIMESTAMP NULL, \"date_opened\" TIMESTAMP NULL, \"latitude\" TEXT NULL, \
+             \"longitude\" TEXT NULL, \"data_owner\" TEXT NOT NULL, \"site_link\" TEXT NOT NULL);"
+        );
+        assert_eq!(
+            insert().values(&rows).sql(&Sqlite, &namespace).unwrap(),
+            "INSERT INTO \"raw_metadata\" (\"scrape_time\", \"site_code\", \"site_name\", \"site_type\", \
+             \"date_closed\", \"date_opened\", \"latitude\", \"longitude\", \"data_owner\", \
+             \"site_link\") VALUES ('2023-11-14 22:13:20.000000', 'MY1', \
+             'Westminster - Marylebone Road', 'Kerbside', NULL, '2000-01-01 00:00:00.000000', \
+             '51.52254', NULL, 'City of Westminster', 'https://www.londonair.org.uk')"
+        );
+    }
+
+    #[tokio::test]
+    async fn reingesting_replaces_readings() {
+        for bulk_load in [false, true] {
+            let conn = MockConnection::new();
+            create::<FlatSensorReading>(&conn).await.unwrap();
+            for value in ["1.5", "2.5"] {
+                let rows = readings(value);
+                if bulk_load {
+                    let load = upsert().load(&rows, LoadFormat::Ndjson).unwrap();
+                    load.execute(&conn).await.unwrap();
+                } else {
+                    upsert()
+                        .values(&rows)
+                        .unwrap()
+                        .execute(&conn)
+                        .await
+                        .unwrap();
+                }
+            }
+
+            // Including the reading without a species, whose key is empty rather than NULL
+            let rows = conn.rows("raw_sensor_reading");
+            assert_eq!(rows.len(), 3);
+            let values: Vec<_> = rows.iter().map(|row| row[4].to_string()).collect();
+            assert_eq!(values, ["2.5", "2.5", "2.5"]);
+        }
+    }
+
+    #[tokio::test]
+    async fn borrowed_rows_read_back_owned() {
+        let conn = MockConnection::new();
+        create::<SiteMeta>(&conn).await.unwrap();
+        let site_code = String::from("MY1");
+        let row = SiteMeta {
+            site_code: Cow::Borrowed(&site_code),
+            ..site_meta()
+        };
+        insert().values([row]).execute(&conn).await.unwrap();
+
+        let fetched = select::<SiteMeta>().fetch(&conn).await.unwrap();
+        drop(site_code);
+        assert_eq!(fetched.len(), 1);
+        assert_eq!(fetched[0].site_code, "MY1");
+        assert_eq!(fetched[0].latitude.as_deref(), Some("51.52254"));
+        assert_eq!(fetched[0].longitude, None);
+    }
 }
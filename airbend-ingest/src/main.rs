@@ -3,11 +3,18 @@ mod client_middleware;
 mod db;
 mod sources;
 
+use std::borrow::Cow;
+use std::future::Future;
+use std::path::Path;
 use std::time::{Duration, Instant};
 
-use airbend_table::{create, insert, Client, Connection};
+use airbend_table::{
+    insert, migrate_all, registry, transaction, upsert, BatchOptions, Client, Connection, Databend,
+    LoadFormat, MigrateOptions, Namespace, Namespaced, WriteStats,
+};
+use anyhow::Context;
 use clap::Parser;
-use cli::Cli;
+use cli::{Cli, Command};
 // Our own crate for DB inserts
 use db::laqn::{FlatSensorReading, SiteMeta};
 use indicatif::{MultiProgress, ProgressBar};
@@ -19,33 +26,83 @@ use tokio::{
     sync::mpsc::{self, Sender},
     task::JoinSet,
 };
-use tracing::{debug, error};
-
-/// Maps the HTTP response to the database representation.
-/// This is the same struct but has an addition scrape_time column.
-fn site_to_site_meta(value: Site, time: jiff::Timestamp) -> SiteMeta {
-    SiteMeta {
-        site_code: value.site_code,
-        site_name: value.site_name,
-        site_type: value.site_type,
-        date_closed: value.date_closed,
-        date_opened: value.date_opened,
-        latitude: value.latitude,
-        longitude: value.longitude,
-        data_owner: value.data_owner,
-        site_link: value.site_link,
-        scrape_time: time,
+use tracing::{debug, error, warn};
+
+/// Attempts at a database write before giving up on a transient failure.
+const MAX_WRITE_ATTEMPTS: u32 = 4;
+
+/// Run a database write, retrying with exponential backoff while it fails with a transient
+/// error (a dropped connection, a conflicting concurrent write). Other errors are returned
+/// straight away. Only use it for writes that can safely run twice, such as upserts.
+async fn retry_transient<T, F, Fut>(mut write: F) -> airbend_table::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = airbend_table::Result<T>>,
+{
+    let mut delay = Duration::from_millis(500);
+    for attempt in 1..MAX_WRITE_ATTEMPTS {
+        match write().await {
+            Err(e) if e.is_transient() => {
+                warn!("Write attempt {} failed, retrying: {}", attempt, e);
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+            result => return result,
+        }
     }
+    write().await
+}
+
+/// Maps the HTTP response to the database representation, borrowing its text.
+/// This is the same struct but has an addition scrape_time column,
+/// and the opening and closing dates are parsed into timestamps.
+fn site_to_site_meta(value: &Site, time: jiff::Timestamp) -> anyhow::Result<SiteMeta<'_>> {
+    Ok(SiteMeta {
+        site_code: value.site_code.as_str().into(),
+        site_name: value.site_name.as_str().into(),
+        site_type: value.site_type.as_str().into(),
+        date_closed: value
+            .date_closed
+            .as_deref()
+            .map(parse_laqn_date)
+            .transpose()?,
+        date_opened: value
+            .date_opened
+            .as_deref()
+            .map(parse_laqn_date)
+            .transpose()?,
+        latitude: value.latitude.as_deref().map(Cow::from),
+        longitude: value.longitude.as_deref().map(Cow::from),
+        data_owner: value.data_owner.as_str().into(),
+        site_link: value.site_link.as_str().into(),
+        scrape_time: time,
+    })
+}
+
+/// LAQN returns dates as 'yyyy-mm-dd hh:mm:ss' in GMT.
+fn parse_laqn_date(date: &str) -> anyhow::Result<jiff::Timestamp> {
+    let datetime: jiff::civil::DateTime = date.parse()?;
+    Ok(datetime.to_zoned(jiff::tz::TimeZone::UTC)?.timestamp())
 }
 
 /// Results from the request and insert to show in terminal
 struct LaqnResult {
     site_code: String,
     n_records: u32,
+    /// Rows and bytes the server reported writing.
+    n_written: u64,
+    bytes_written: u64,
 }
 
 /// Ingest data calling the LAQN network and inserting into databend
 async fn ingest_data(args: Cli, tx: Sender<LaqnResult>) -> anyhow::Result<()> {
+    // Every statement runs in the requested database and table prefix
+    let namespace = cli_namespace(&args);
+
+    // Only optional when a subcommand is run instead
+    let start_date = args.start_date.context("--start-date is required")?;
+    let end_date = args.end_date.context("--end-date is required")?;
+
     // Create an http client
     let client = create_client(args.max_concurrent_connections)?;
 
@@ -57,64 +114,124 @@ async fn ingest_data(args: Cli, tx: Sender<LaqnResult>) -> anyhow::Result<()> {
     };
 
     let db_client = Client::new(dsn);
-    let conn = db_client.get_conn().await.unwrap();
 
-    // Create a Site Metadata table
-    create::<SiteMeta>(&*conn).await?;
-    create::<FlatSensorReading>(&*conn).await?;
+    let conn = namespace
+        .clone()
+        .connect(db_client.get_conn().await.unwrap());
+
+    // Create every table, or bring existing tables in line with their definitions.
+    let migrate_options =
+        MigrateOptions::default().allow_destructive(args.allow_destructive_migrations);
+    for migration in migrate_all(&conn, migrate_options).await? {
+        for warning in &migration.warnings {
+            warn!("{}", warning);
+        }
+        if !migration.is_empty() {
+            debug!("Applied migration:\n{}", migration);
+        }
+    }
 
     // Records the scrape time.
     let scrape_time = Timestamp::now().round(Unit::Second)?;
 
-    // Get site metadata from REST API.
+    // Get site metadata from REST API. Each site's metadata is written along with its
+    // readings.
     let meta = get_meta(&client).await.unwrap();
 
-    // Add the scrape time.
-    let db_meta: Vec<SiteMeta> = meta
-        .sites
-        .site
-        .clone()
-        .into_iter()
-        .map(|r| site_to_site_meta(r, scrape_time))
-        .collect();
-
-    // Insert the values into the database
-    insert().values(db_meta).execute(&*conn).await?;
-
     // This is a collection that keeps track of async tasks. Each task is a call to an
     // API endpoint + an insert into the database.
     let mut request_joinset: JoinSet<anyhow::Result<String>> = JoinSet::new();
 
+    #[allow(clippy::too_many_arguments)]
     async fn get_sensor_data_and_insert(
         tx: Sender<LaqnResult>,
         client: reqwest_middleware::ClientWithMiddleware,
-        conn: Box<dyn Connection>,
+        conn: Namespaced<Box<dyn Connection>>,
         sensor_site: Site,
         start_date: String,
         end_date: String,
         scrape_time: Timestamp,
+        bulk_load: bool,
     ) -> anyhow::Result<String> {
-        if let Ok(values) =
-            get_raw_laqn_readings(&client, &sensor_site.site_code, &start_date, &end_date).await
-        {
-            let mut insert_rows = vec![];
-            let mut n_records = 0;
-            for value in values.air_quality_data.readings {
-                insert_rows.push(FlatSensorReading {
-                    site_code: sensor_site.site_code.clone(),
-                    measurement_date: value.measurement_date,
-                    species_code: value.species_code,
-                    value: value.value,
-                    scrape_time,
-                });
-                n_records += 1;
-            }
+        let site_meta = site_to_site_meta(&sensor_site, scrape_time)?;
+        // The metadata is written even if the readings cannot be fetched
+        let readings =
+            get_raw_laqn_readings(&client, &sensor_site.site_code, &start_date, &end_date)
+                .await
+                .ok();
+        let fetched = readings.is_some();
+        let insert_rows: Vec<FlatSensorReading> = readings
+            .into_iter()
+            .flat_map(|values| values.air_quality_data.readings)
+            .map(|value| FlatSensorReading {
+                site_code: sensor_site.site_code.as_str().into(),
+                measurement_date: value.measurement_date,
+                // Keys cannot be NULL, or re-ingesting would duplicate the reading
+                species_code: value.species_code.unwrap_or_default(),
+                value: value.value,
+                scrape_time,
+            })
+            .collect();
+        let n_records = insert_rows.len() as u32;
+
+        // Upsert so that re-ingesting the same window replaces readings instead of duplicating them.
+        let load = if bulk_load && !insert_rows.is_empty() {
+            Some(upsert().load(&insert_rows, LoadFormat::Ndjson)?)
+        } else {
+            None
+        };
+        // The site's metadata and readings are written in one transaction, so a failed write
+        // leaves none of them behind and can be retried as a whole.
+        let (site_meta, insert_rows, load) = (&site_meta, &insert_rows, &load);
+        let stats = retry_transient(|| {
+            transaction(&conn, |tx| async move {
+                insert()
+                    .values(std::slice::from_ref(site_meta))
+                    .execute(&tx)
+                    .await?;
+                if let Some(load) = load {
+                    return load.execute(&tx).await;
+                }
+                let outcomes = upsert()
+                    .batched(insert_rows, BatchOptions::default())?
+                    .execute(&tx)
+                    .await;
+
+                let mut stats = WriteStats::default();
+                let mut errors = vec![];
+                for outcome in outcomes {
+                    match outcome.result {
+                        Ok(batch) => stats.add(&batch),
+                        Err(e) => errors.push(e),
+                    }
+                }
 
-            insert().values(insert_rows).execute(&*conn).await?;
+                // Report a failure that retrying cannot fix ahead of transient ones
+                errors.sort_by_key(|e| e.is_transient());
+                errors.into_iter().next().map_or(Ok(stats), Err)
+            })
+        })
+        .await
+        .with_context(|| format!("Writing site {} failed", sensor_site.site_code))?;
+
+        if fetched {
+            // Check the server wrote every reading that was sent
+            debug!(
+                "Wrote {} readings ({} bytes) for site {} in {:?}",
+                stats.rows, stats.bytes, sensor_site.site_code, stats.elapsed
+            );
+            if stats.rows != n_records as u64 {
+                warn!(
+                    "Sent {} readings for site {} but the server wrote {}",
+                    n_records, sensor_site.site_code, stats.rows
+                );
+            }
 
             tx.send(LaqnResult {
                 site_code: sensor_site.site_code.clone(),
                 n_records,
+                n_written: stats.rows,
+                bytes_written: stats.bytes,
             })
             .await
             .expect("Could not send result in channel")
@@ -123,16 +240,19 @@ async fn ingest_data(args: Cli, tx: Sender<LaqnResult>) -> anyhow::Result<()> {
         Ok(sensor_site.site_code)
     }
 
-    // Start all request and insert tasks.
+    // Start all request and insert tasks. Each task gets its own connection, since a
+    // transaction belongs to the connection's session.
     for sensor_site in meta.sites.site.into_iter() {
+        let site_conn = namespace.clone().connect(db_client.get_conn().await?);
         request_joinset.spawn(get_sensor_data_and_insert(
             tx.clone(),
             client.clone(),
-            conn.clone(),
+            site_conn,
             sensor_site,
-            args.start_date.clone(),
-            args.end_date.clone(),
+            start_date.clone(),
+            end_date.clone(),
             scrape_time,
+            args.bulk_load,
         ));
     }
 
@@ -157,10 +277,52 @@ async fn ingest_data(args: Cli, tx: Sender<LaqnResult>) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Where --database and --table-prefix place the tables.
+fn cli_namespace(args: &Cli) -> Namespace {
+    let namespace = Namespace::new().table_prefix(&args.table_prefix);
+    match &args.database {
+        Some(database) => namespace.database(database),
+        None => namespace,
+    }
+}
+
+/// Write the output of a subcommand to `path`, or to stdout if there is none.
+fn write_output(path: Option<&Path>, contents: &str) -> anyhow::Result<()> {
+    match path {
+        Some(path) => std::fs::write(path, contents)
+            .with_context(|| format!("Failed to write {}", path.display())),
+        None => {
+            print!("{}", contents);
+            Ok(())
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let args = Cli::parse();
 
+    match &args.command {
+        Some(Command::DbtSources {
+            output,
+            source,
+            schema,
+        }) => {
+            let yml = airbend_table::dbt::sources_yml(
+                source,
+                schema,
+                &cli_namespace(&args),
+                &registry::tables(),
+            )?;
+            return write_output(output.as_deref(), &yml);
+        }
+        Some(Command::Ddl { output }) => {
+            let ddl = registry::ddl(&Databend, &cli_namespace(&args));
+            return write_output(output.as_deref(), &ddl);
+        }
+        None => {}
+    }
+
     // Create a channel for communicating between the ingestor (gets data and inserts to database)
     // and the progress bar handle (updates the terminal interface)
     let (tx, mut rx) = mpsc::channel::<LaqnResult>(100);
@@ -169,6 +331,8 @@ async fn main() -> anyhow::Result<()> {
     let progress_handle = tokio::task::spawn_blocking(move || {
         let start = Instant::now();
         let mut count = 0;
+        let mut total_written = 0;
+        let mut total_bytes = 0;
 
         let multi_progress = MultiProgress::new();
         let header = multi_progress.add(ProgressBar::new_spinner());
@@ -183,17 +347,22 @@ async fn main() -> anyhow::Result<()> {
 
         while let Some(result) = rx.blocking_recv() {
             count += 1;
+            total_written += result.n_written;
+            total_bytes += result.bytes_written;
             let duration = start.elapsed();
             let seconds = duration.as_secs() % 60;
             let minutes = (duration.as_secs() / 60) % 60;
             visit_stats.set_message(format!(
-                "  Inserted {} LAQN sites in {:0>2}:{:0>2}",
+                "  Inserted {} LAQN sites ({} rows, {} bytes) in {:0>2}:{:0>2}",
                 count.cyan(),
+                total_written.cyan(),
+                total_bytes.cyan(),
                 minutes.to_string().cyan(),
                 seconds.to_string().cyan()
             ));
             current_url.set_message(format!(
-                "  Inserted {} records for site: {}",
+                "  Inserted {} of {} records for site: {}",
+                result.n_written.green(),
                 result.n_records.green(),
                 result.site_code
             ));
@@ -1,6 +1,5 @@
 use std::time::Duration;
 
-use airbend_table::AirbendTable;
 use reqwest::redirect;
 use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
 use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
@@ -26,33 +25,25 @@ where
 }
 
 #[allow(unused)]
-#[derive(Deserialize, Debug, Clone, AirbendTable)]
-#[airbend_table(table_name = "raw_metadata")]
+#[derive(Deserialize, Debug, Clone)]
 pub struct Site {
     #[serde(alias = "@LocalAuthorityCode")]
     pub local_authority_code: String,
     #[serde(alias = "@LocalAuthorityName")]
     pub local_authority_name: String,
     #[serde(alias = "@SiteCode")]
-    #[airbend_col(dtype = "VARCHAR")]
     pub site_code: String,
     #[serde(alias = "@SiteName")]
-    #[airbend_col(dtype = "VARCHAR")]
     pub site_name: String,
     #[serde(alias = "@SiteType")]
-    #[airbend_col(dtype = "VARCHAR")]
     pub site_type: String,
     #[serde(alias = "@DateClosed", deserialize_with = "empty_string_as_none")]
-    #[airbend_col(dtype = "TIMESTAMP")]
     pub date_closed: Option<String>,
     #[serde(alias = "@DateOpened", deserialize_with = "empty_string_as_none")]
-    #[airbend_col(dtype = "TIMESTAMP")]
     pub date_opened: Option<String>,
     #[serde(alias = "@Latitude", deserialize_with = "empty_string_as_none")]
-    #[airbend_col(dtype = "VARCHAR")]
     pub latitude: Option<String>,
     #[serde(alias = "@Longitude", deserialize_with = "empty_string_as_none")]
-    #[airbend_col(dtype = "VARCHAR")]
     pub longitude: Option<String>,
     #[serde(alias = "@LatitudeWGS84")]
     pub latitude_wgs84: String,
@@ -63,12 +54,10 @@ pub struct Site {
     #[serde(alias = "@DisplayOffsetY")]
     pub display_offset_y: String,
     #[serde(alias = "@DataOwner")]
-    #[airbend_col(dtype = "VARCHAR")]
     pub data_owner: String,
     #[serde(alias = "@DataManager")]
     pub display_manager: String,
     #[serde(alias = "@SiteLink")]
-    #[airbend_col(dtype = "VARCHAR")]
     pub site_link: String,
 }
 
@@ -110,29 +99,6 @@ pub struct SensorReading {
     pub value: Option<String>,
 }
 
-#[derive(AirbendTable)]
-#[airbend_table(table_name = "raw_metadata")]
-pub struct SiteMeta {
-    #[airbend_col(dtype = "VARCHAR")]
-    pub site_code: String,
-    #[airbend_col(dtype = "VARCHAR")]
-    pub site_name: String,
-    #[airbend_col(dtype = "VARCHAR")]
-    pub site_type: String,
-    #[airbend_col(dtype = "TIMESTAMP")]
-    pub date_closed: Option<String>,
-    #[airbend_col(dtype = "TIMESTAMP")]
-    pub date_opened: Option<String>,
-    #[airbend_col(dtype = "VARCHAR")]
-    pub latitude: Option<String>,
-    #[airbend_col(dtype = "VARCHAR")]
-    pub longitude: Option<String>,
-    #[airbend_col(dtype = "VARCHAR")]
-    pub data_owner: String,
-    #[airbend_col(dtype = "VARCHAR")]
-    pub site_link: String,
-}
-
 pub fn create_client(max_concurrent_requests: usize) -> anyhow::Result<ClientWithMiddleware> {
     let retry_policy = ExponentialBackoff::builder()
         .jitter(reqwest_retry::Jitter::Bounded)
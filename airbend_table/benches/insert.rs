@@ -0,0 +1,139 @@
+//! Compares rendering an insert from borrowed rows with the clone-and-convert approach it
+//! replaces, and with serializing the same rows to a CSV or NDJSON file for a bulk load.
+//! Run with `cargo bench -p airbend_table --bench insert`.
+//!
+//! Counts heap allocations with a wrapping allocator, as well as timing each approach.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use airbend_table::{insert, AirbendTable, Databend, LoadFormat, Namespace, Table};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// The shape of the readings written by airbend-ingest.
+#[derive(AirbendTable, Clone)]
+#[airbend_table(table_name = "raw_sensor_reading")]
+struct Reading {
+    scrape_time: jiff::Timestamp,
+    #[airbend_col(key)]
+    site_code: String,
+    #[airbend_col(key)]
+    measurement_date: String,
+    #[airbend_col(key)]
+    species_code: String,
+    value: Option<String>,
+}
+
+const ROWS: usize = 100_000;
+const RUNS: u32 = 10;
+
+fn readings() -> Vec<Reading> {
+    let scrape_time = jiff::Timestamp::from_second(1_700_000_000).unwrap();
+    (0..ROWS)
+        .map(|i| Reading {
+            scrape_time,
+            site_code: format!("SITE{}", i % 100),
+            measurement_date: format!("2024-01-01 {:02}:00:00", i % 24),
+            species_code: "NO2".to_string(),
+            value: (i % 7 != 0).then(|| format!("{}.5", i % 90)),
+        })
+        .collect()
+}
+
+/// Statement rendering as it was before rows could be borrowed: the rows are cloned to be
+/// given to the query, and every row is converted to a `Vec<InsertValue>` before being written.
+fn clone_and_convert(rows: &[Reading]) -> String {
+    use std::fmt::Write;
+    let mut sql = String::from("INSERT INTO `raw_sensor_reading` VALUES ");
+    for (i, row) in rows.iter().cloned().enumerate() {
+        if i > 0 {
+            sql.push(',');
+        }
+        sql.push('(');
+        for (j, value) in row.to_row().unwrap().iter().enumerate() {
+            if j > 0 {
+                sql.push_str(", ");
+            }
+            write!(sql, "{}", value).unwrap();
+        }
+        sql.push(')');
+    }
+    sql
+}
+
+/// Time `f` over [RUNS] runs, and count the allocations of one run. `f` returns the size
+/// of what it rendered.
+fn measure(name: &str, mut f: impl FnMut() -> usize) {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let bytes = black_box(f());
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+
+    let mut total = Duration::ZERO;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        black_box(f());
+        total += start.elapsed();
+    }
+    println!(
+        "{:<28} {:>8.2?} per run  {:>6.2} allocations per row  ({} bytes)",
+        name,
+        total / RUNS,
+        allocations as f64 / ROWS as f64,
+        bytes
+    );
+}
+
+fn main() {
+    let rows = readings();
+    let namespace = Namespace::new();
+    println!("Rendering an insert of {} rows", ROWS);
+    measure("clone and convert (before)", || {
+        clone_and_convert(&rows).len()
+    });
+    measure("values(Vec<T>) with a clone", || {
+        insert()
+            .values(rows.clone())
+            .sql(&Databend, &namespace)
+            .unwrap()
+            .len()
+    });
+    measure("values(&[T])", || {
+        insert()
+            .values(&rows)
+            .sql(&Databend, &namespace)
+            .unwrap()
+            .len()
+    });
+
+    println!("Serializing {} rows for a bulk load", ROWS);
+    measure("load(&[T], Csv)", || {
+        insert().load(&rows, LoadFormat::Csv).unwrap().bytes()
+    });
+    measure("load(&[T], Ndjson)", || {
+        insert().load(&rows, LoadFormat::Ndjson).unwrap().bytes()
+    });
+}
@@ -0,0 +1,335 @@
+use futures::StreamExt;
+
+use crate::dialect::Dialect;
+use crate::error::Result;
+use crate::executor::{Executor, WriteStats};
+use crate::row::write_row;
+use crate::tables::{Insert, Statement, Table, Upsert};
+
+/// Controls how rows are split into separate `INSERT` statements.
+///
+/// A batch is closed once it holds `max_rows` rows or adding another row would take the
+/// statement over `max_bytes`. A single row larger than `max_bytes` is sent on its own.
+#[derive(Debug, Clone)]
+pub struct BatchOptions {
+    max_rows: Option<usize>,
+    max_bytes: Option<usize>,
+    concurrency: usize,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            max_rows: Some(10_000),
+            max_bytes: Some(4 * 1024 * 1024),
+            concurrency: 1,
+        }
+    }
+}
+
+impl BatchOptions {
+    /// Maximum number of rows per statement. `None` removes the limit.
+    pub fn max_rows(mut self, max_rows: impl Into<Option<usize>>) -> Self {
+        self.max_rows = max_rows.into();
+        self
+    }
+
+    /// Maximum size of a statement in bytes. `None` removes the limit.
+    pub fn max_bytes(mut self, max_bytes: impl Into<Option<usize>>) -> Self {
+        self.max_bytes = max_bytes.into();
+        self
+    }
+
+    /// Number of batches allowed to run at the same time. Defaults to 1 (sequential).
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+}
+
+/// The result of running a single batch.
+#[derive(Debug)]
+pub struct BatchOutcome {
+    /// Position of the batch, starting at 0.
+    pub index: usize,
+    /// Number of rows in the batch.
+    pub rows: usize,
+    /// Size of the statement sent to the server.
+    pub bytes: usize,
+    /// What the server reports the batch wrote, or why it failed.
+    pub result: Result<WriteStats>,
+}
+
+impl BatchOutcome {
+    pub fn is_ok(&self) -> bool {
+        self.result.is_ok()
+    }
+}
+
+/// An insert split into several statements. Created with [Insert::batched] or [Upsert::batched].
+/// Like a [crate::Query], takes the rows over or borrows them for `'a`.
+pub struct BatchInsert<'a, T> {
+    statement: Statement,
+    rows: Box<dyn AsRef<[T]> + Send + Sync + 'a>,
+    options: BatchOptions,
+}
+
+impl<T: Table> Insert<T> {
+    pub fn batched<'a, V>(self, values: V, options: BatchOptions) -> BatchInsert<'a, T>
+    where
+        V: AsRef<[T]> + Send + Sync + 'a,
+    {
+        BatchInsert {
+            statement: Statement::insert::<T>(),
+            rows: Box::new(values),
+            options,
+        }
+    }
+}
+
+impl<T: Table> Upsert<T> {
+    pub fn batched<'a, V>(self, values: V, options: BatchOptions) -> Result<BatchInsert<'a, T>>
+    where
+        V: AsRef<[T]> + Send + Sync + 'a,
+    {
+        Ok(BatchInsert {
+            statement: Statement::upsert::<T>()?,
+            rows: Box::new(values),
+            options,
+        })
+    }
+}
+
+impl<T: Table> BatchInsert<'_, T> {
+    /// Run every batch and report the outcome of each, in batch order.
+    /// A failed batch does not stop the remaining batches from running.
+    /// Statements are rendered as they are needed, so at most `concurrency` are held in memory.
+    pub async fn execute(self, conn: &(impl Executor + ?Sized)) -> Vec<BatchOutcome> {
+        let concurrency = self.options.concurrency;
+        let dialect = conn.dialect();
+        let batches = Batches {
+            prefix: self.statement.prefix(dialect, conn.namespace()),
+            dialect,
+            rows: (*self.rows).as_ref().iter(),
+            pending: None,
+            options: self.options,
+            index: 0,
+        };
+
+        futures::stream::iter(batches)
+            .map(|batch| async move {
+                let (bytes, result) = match batch.sql {
+                    Ok(sql) => (sql.len(), conn.write(&sql).await),
+                    Err(e) => (0, Err(e)),
+                };
+                BatchOutcome {
+                    index: batch.index,
+                    rows: batch.rows,
+                    bytes,
+                    result,
+                }
+            })
+            .buffered(concurrency)
+            .collect()
+            .await
+    }
+}
+
+struct Batch {
+    index: usize,
+    rows: usize,
+    /// The statement, or the error of a row that could not be rendered, which fails the
+    /// batch without sending it.
+    sql: Result<String>,
+}
+
+/// Lazily renders rows into insert statements that respect [BatchOptions].
+struct Batches<'a, T> {
+    /// Statement text that comes before the rows.
+    prefix: String,
+    dialect: &'a dyn Dialect,
+    rows: std::slice::Iter<'a, T>,
+    /// A rendered row that did not fit in the previous batch.
+    pending: Option<String>,
+    options: BatchOptions,
+    index: usize,
+}
+
+impl<T: Table> Iterator for Batches<'_, T> {
+    type Item = Batch;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut sql = self.prefix.clone();
+        let mut rows = 0;
+        let mut error = None;
+
+        loop {
+            let row = match self.pending.take() {
+                Some(row) => row,
+                None => match self.rows.next() {
+                    Some(row) => {
+                        let mut rendered = String::new();
+                        if let Err(e) = write_row(&mut rendered, self.dialect, row) {
+                            rows += 1;
+                            error = Some(e);
+                            break;
+                        }
+                        rendered
+                    }
+                    None => break,
+                },
+            };
+
+            if rows > 0 {
+                if let Some(max_bytes) = self.options.max_bytes {
+                    // +1 for the separating comma
+                    if sql.len() + 1 + row.len() > max_bytes {
+                        self.pending = Some(row);
+                        break;
+                    }
+                }
+                sql.push(',');
+            }
+            sql.push_str(&row);
+            rows += 1;
+
+            if self
+                .options
+                .max_rows
+                .is_some_and(|max_rows| rows >= max_rows)
+            {
+                break;
+            }
+        }
+
+        if rows == 0 {
+            return None;
+        }
+
+        let batch = Batch {
+            index: self.index,
+            rows,
+            sql: match error {
+                Some(e) => Err(e),
+                None => Ok(sql),
+            },
+        };
+        self.index += 1;
+        Some(batch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::mock::MockConnection;
+    use crate::{create, insert, AirbendTable, Databend, Namespace};
+
+    #[derive(AirbendTable)]
+    #[airbend_table(table_name = "reading")]
+    struct Reading {
+        site_code: String,
+        value: f64,
+    }
+
+    fn readings(site_codes: &[&str]) -> Vec<Reading> {
+        site_codes
+            .iter()
+            .map(|site_code| Reading {
+                site_code: site_code.to_string(),
+                value: 1.5,
+            })
+            .collect()
+    }
+
+    /// The size of the statement inserting `rows` on its own.
+    fn statement_bytes(rows: &[Reading]) -> usize {
+        insert()
+            .values(rows)
+            .sql(&Databend, &Namespace::default())
+            .unwrap()
+            .len()
+    }
+
+    async fn run(rows: &[Reading], options: BatchOptions) -> (MockConnection, Vec<BatchOutcome>) {
+        let conn = MockConnection::new();
+        create::<Reading>(&conn).await.unwrap();
+        let outcomes = insert().batched(rows, options).execute(&conn).await;
+        (conn, outcomes)
+    }
+
+    fn batch_rows(outcomes: &[BatchOutcome]) -> Vec<usize> {
+        outcomes.iter().map(|outcome| outcome.rows).collect()
+    }
+
+    #[tokio::test]
+    async fn splits_on_max_rows() {
+        let rows = readings(&["MY1", "MY2", "MY3", "MY4", "MY5"]);
+        let (conn, outcomes) = run(&rows, BatchOptions::default().max_rows(2)).await;
+
+        assert_eq!(batch_rows(&outcomes), [2, 2, 1]);
+        assert!(outcomes.iter().all(BatchOutcome::is_ok));
+        assert_eq!(conn.rows("reading").len(), 5);
+    }
+
+    #[tokio::test]
+    async fn splits_on_max_bytes() {
+        let rows = readings(&["MY1", "MY2", "MY3", "MY4", "MY5"]);
+        let max_bytes = statement_bytes(&rows[..2]);
+        let options = BatchOptions::default().max_rows(None).max_bytes(max_bytes);
+        let (conn, outcomes) = run(&rows, options).await;
+
+        assert_eq!(batch_rows(&outcomes), [2, 2, 1]);
+        assert!(outcomes.iter().all(|outcome| outcome.bytes <= max_bytes));
+        assert_eq!(conn.rows("reading").len(), 5);
+    }
+
+    #[tokio::test]
+    async fn sends_an_oversized_row_alone() {
+        let long = "X".repeat(100);
+        let rows = readings(&["MY1", &long, "MY3", "MY4"]);
+        let max_bytes = statement_bytes(&rows[2..]);
+        let options = BatchOptions::default().max_rows(None).max_bytes(max_bytes);
+        let (conn, outcomes) = run(&rows, options).await;
+
+        assert_eq!(batch_rows(&outcomes), [1, 1, 2]);
+        assert_eq!(outcomes[1].bytes, statement_bytes(&rows[1..2]));
+        assert!(outcomes[1].bytes > max_bytes);
+        assert!(outcomes.iter().all(BatchOutcome::is_ok));
+        assert_eq!(conn.rows("reading").len(), 4);
+    }
+
+    #[tokio::test]
+    async fn a_failed_batch_does_not_stop_the_others() {
+        let rows = readings(&["MY1", "MY2", "MY3", "MY4", "MY5"]);
+        let conn = MockConnection::new();
+        create::<Reading>(&conn).await.unwrap();
+        conn.fail_on("MY3", Error::Connection("connection reset".to_string()));
+
+        let outcomes = insert()
+            .batched(&rows, BatchOptions::default().max_rows(2))
+            .execute(&conn)
+            .await;
+        let failed: Vec<_> = outcomes.iter().map(|outcome| !outcome.is_ok()).collect();
+        assert_eq!(failed, [false, true, false]);
+        assert!(matches!(outcomes[1].result, Err(Error::Connection(_))));
+        assert_eq!(conn.rows("reading").len(), 3);
+    }
+
+    #[tokio::test]
+    async fn concurrent_outcomes_stay_in_batch_order() {
+        let rows = readings(&["A", "BB", "CCC", "DDDD", "EEEEE", "FFFFFF"]);
+        let options = BatchOptions::default().max_rows(1).concurrency(3);
+        let (conn, outcomes) = run(&rows, options).await;
+
+        for (i, outcome) in outcomes.iter().enumerate() {
+            assert_eq!(outcome.index, i);
+            assert_eq!(outcome.rows, 1);
+            assert_eq!(outcome.bytes, statement_bytes(&rows[i..i + 1]));
+            assert!(outcome.is_ok());
+        }
+        assert_eq!(outcomes.len(), rows.len());
+        assert_eq!(conn.rows("reading").len(), rows.len());
+    }
+}
@@ -0,0 +1,193 @@
+//! dbt source definitions generated from [crate::Table] types, so a dbt project and the Rust
+//! definitions of its raw tables cannot drift apart.
+
+use std::fmt::{self, Write};
+
+use crate::error::{Error, Result};
+use crate::namespace::Namespace;
+use crate::registry::TableDef;
+
+/// A dbt `sources.yml` declaring `tables` as source `source`, placed by `namespace`.
+///
+/// The source is found in database schema `schema`, unless the namespace or the tables
+/// themselves name a database. Tables keep their own names in dbt; a name changed by the
+/// namespace is given as the table's `identifier`. Each column lists its type and its doc comment as the description.
+/// Columns that are not nullable get a `not_null` test.
+///
+/// dbt sets the schema per source, so every table must end up in the same database.
+pub fn sources_yml(
+    source: &str,
+    schema: &str,
+    namespace: &Namespace,
+    tables: &[&TableDef],
+) -> Result<String> {
+    // Tables without a database are in the connection's, which dbt knows as `schema`
+    let schema_of = |table: &TableDef| namespace.database_of(table).unwrap_or(schema).to_string();
+    let schema = tables
+        .first()
+        .map_or(schema.to_string(), |table| schema_of(table));
+    if let Some(table) = tables.iter().find(|table| schema_of(table) != schema) {
+        return Err(Error::Generation(format!(
+            "table {} is in database {}, not in schema {} of dbt source {}",
+            table.name(),
+            schema_of(table),
+            schema,
+            source
+        )));
+    }
+    let mut yml = String::new();
+    write_sources_yml(&mut yml, source, &schema, namespace, tables)
+        .expect("writing to a String cannot fail");
+    Ok(yml)
+}
+
+fn write_sources_yml(
+    yml: &mut impl Write,
+    source: &str,
+    schema: &str,
+    namespace: &Namespace,
+    tables: &[&TableDef],
+) -> fmt::Result {
+    writeln!(
+        yml,
+        "# Generated from the airbend_table definitions. Do not edit by hand."
+    )?;
+    writeln!(yml, "version: 2")?;
+    writeln!(yml)?;
+    writeln!(yml, "sources:")?;
+    writeln!(yml, "  - name: {}", yaml_string(source))?;
+    writeln!(yml, "    schema: {}", yaml_string(schema))?;
+    writeln!(yml, "    tables:")?;
+    for table in tables {
+        writeln!(yml, "      - name: {}", yaml_string(table.name()))?;
+        let identifier = namespace.table_name(table);
+        if identifier != table.name() {
+            writeln!(yml, "        identifier: {}", yaml_string(&identifier))?;
+        }
+        if let Some(comment) = table.options().comment {
+            writeln!(yml, "        description: {}", yaml_string(comment))?;
+        }
+        writeln!(yml, "        columns:")?;
+        for field in table.schema() {
+            writeln!(yml, "          - name: {}", yaml_string(field.name))?;
+            let data_type = field.data_type.to_string();
+            writeln!(yml, "            data_type: {}", yaml_string(&data_type))?;
+            if let Some(comment) = field.comment {
+                writeln!(yml, "            description: {}", yaml_string(comment))?;
+            }
+            if !field.nullable {
+                writeln!(yml, "            data_tests:")?;
+                writeln!(yml, "              - not_null")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// `s` as a YAML scalar. Anything beyond simple names is double quoted, using JSON escapes
+/// which YAML shares. Words YAML reads as booleans or null (`on`, `no`, ...) are quoted too.
+fn yaml_string(s: &str) -> String {
+    const RESERVED: [&str; 9] = ["y", "n", "yes", "no", "on", "off", "true", "false", "null"];
+    let is_plain = s
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !RESERVED.contains(&s.to_ascii_lowercase().as_str());
+    if is_plain {
+        s.to_string()
+    } else {
+        serde_json::Value::from(s).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AirbendTable;
+
+    /// Hourly readings from a sensor.
+    #[derive(AirbendTable)]
+    #[airbend_table(table_name = "reading")]
+    struct Reading {
+        /// Code of the site
+        site_code: String,
+        value: Option<f64>,
+    }
+
+    #[derive(AirbendTable)]
+    #[airbend_table(table_name = "site", database = "meta")]
+    struct Site {
+        site_code: String,
+    }
+
+    #[test]
+    fn describes_tables() {
+        let yml = sources_yml(
+            "laqn",
+            "default",
+            &Namespace::new(),
+            &[&TableDef::of::<Reading>()],
+        )
+        .unwrap();
+        assert_eq!(
+            yml,
+            r#"# Generated from the airbend_table definitions. Do not edit by hand.
+version: 2
+
+sources:
+  - name: laqn
+    schema: default
+    tables:
+      - name: reading
+        description: "Hourly readings from a sensor."
+        columns:
+          - name: site_code
+            data_type: VARCHAR
+            description: "Code of the site"
+            data_tests:
+              - not_null
+          - name: value
+            data_type: DOUBLE
+"#
+        );
+    }
+
+    #[test]
+    fn places_tables_by_namespace() {
+        let namespace = Namespace::new().database("dev").table_prefix("alice_");
+        let yml =
+            sources_yml("laqn", "default", &namespace, &[&TableDef::of::<Reading>()]).unwrap();
+        assert!(yml.contains("    schema: dev\n"), "{}", yml);
+        assert!(
+            yml.contains("      - name: reading\n        identifier: alice_reading\n"),
+            "{}",
+            yml
+        );
+
+        let yml = sources_yml(
+            "laqn",
+            "default",
+            &namespace,
+            &[&TableDef::of::<Reading>(), &TableDef::of::<Site>()],
+        )
+        .unwrap();
+        assert!(yml.contains("        identifier: alice_site\n"), "{}", yml);
+    }
+
+    #[test]
+    fn rejects_tables_in_several_databases() {
+        let error = sources_yml(
+            "laqn",
+            "default",
+            &Namespace::new(),
+            &[&TableDef::of::<Reading>(), &TableDef::of::<Site>()],
+        )
+        .unwrap_err();
+        assert!(
+            matches!(&error, Error::Generation(message) if message.contains("table site is in database meta")),
+            "{}",
+            error
+        );
+    }
+}
@@ -0,0 +1,51 @@
+use std::marker::PhantomData;
+
+use crate::dialect::Dialect;
+use crate::error::{Error, Result};
+use crate::executor::Executor;
+use crate::filter::{where_methods, Filter};
+use crate::namespace::Namespace;
+use crate::registry::TableDef;
+use crate::tables::{InsertValue, Table};
+
+/// A `DELETE` of the rows of `T`'s table that match every condition. Created with [delete].
+///
+/// At least one condition is required. Use [crate::truncate] to remove every row.
+pub struct Delete<T> {
+    filter: Filter,
+    data_type: PhantomData<T>,
+}
+
+pub fn delete<T: Table>() -> Delete<T> {
+    Delete {
+        filter: Filter::new(),
+        data_type: PhantomData,
+    }
+}
+
+impl<T: Table> Delete<T> {
+    where_methods!();
+
+    /// The SQL for this delete in `dialect`, from the table's name in `namespace`. Fails if
+    /// any unknown column was referenced or there are no conditions.
+    pub fn sql(&self, dialect: &dyn Dialect, namespace: &Namespace) -> Result<String> {
+        self.filter.validate::<T>()?;
+        if self.filter.is_empty() {
+            return Err(Error::Generation(format!(
+                "Refusing to delete every row of table {}. Use truncate instead",
+                T::name()
+            )));
+        }
+
+        let table = namespace.table(dialect, &TableDef::of::<T>());
+        let mut sql = format!("DELETE FROM {}", table);
+        self.filter.write_where(&mut sql, dialect)?;
+        Ok(sql)
+    }
+
+    /// Run the delete, returning the number of rows removed.
+    pub async fn execute(&self, conn: &(impl Executor + ?Sized)) -> Result<i64> {
+        conn.exec(&self.sql(conn.dialect(), conn.namespace())?)
+            .await
+    }
+}
@@ -0,0 +1,272 @@
+use std::fmt;
+
+use databend_driver::{NumberValue, Value};
+
+use super::{identifier_list, write_decimal, Dialect};
+use crate::tables::{Field, InsertValue, TableOptions};
+use crate::types::ColumnType;
+
+/// SQL understood by databend. This is the dialect of [databend_driver::Connection].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Databend;
+
+impl Dialect for Databend {
+    fn name(&self) -> &'static str {
+        "databend"
+    }
+
+    fn column_type(&self, column_type: &ColumnType) -> String {
+        column_type.to_string()
+    }
+
+    fn write_literal(&self, f: &mut fmt::Formatter<'_>, value: &Value) -> fmt::Result {
+        write_literal(f, value)
+    }
+
+    fn write_string(&self, f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+        write_quoted(f, s)
+    }
+
+    fn upsert_prefix(&self, table: &str, columns: &[&str], keys: &[&str]) -> String {
+        format!(
+            "REPLACE INTO {} ({}) ON ({}) VALUES ",
+            table,
+            identifier_list(self, columns),
+            identifier_list(self, keys)
+        )
+    }
+
+    fn quote_identifier(&self, name: &str) -> String {
+        format!("`{}`", name.replace('`', "``"))
+    }
+
+    fn column_definition(&self, field: &Field) -> String {
+        let nullable = if field.nullable { "NULL" } else { "NOT NULL" };
+        let mut definition = format!(
+            "{} {} {}",
+            self.quote_identifier(field.name),
+            self.column_type(&field.data_type),
+            nullable
+        );
+        if let Some(comment) = field.comment {
+            definition.push_str(&format!(" COMMENT {}", InsertValue::from(comment)));
+        }
+        definition
+    }
+
+    fn create_table(&self, table: &str, fields: &[Field], options: &TableOptions) -> String {
+        let columns: Vec<_> = fields
+            .iter()
+            .map(|field| self.column_definition(field))
+            .collect();
+        let transient = if options.transient { "TRANSIENT " } else { "" };
+        let mut sql = format!(
+            "CREATE {}TABLE IF NOT EXISTS {} ({})",
+            transient,
+            table,
+            columns.join(", ")
+        );
+        if !options.cluster_by.is_empty() {
+            sql.push_str(&format!(
+                " CLUSTER BY ({})",
+                identifier_list(self, &options.cluster_by)
+            ));
+        }
+        for (option, value) in &options.options {
+            sql.push_str(&format!(" {} = {}", option, InsertValue::from(*value)));
+        }
+        if let Some(comment) = options.comment {
+            sql.push_str(&format!(" COMMENT = {}", InsertValue::from(comment)));
+        }
+        sql.push(';');
+        sql
+    }
+}
+
+/// Write a value as a databend SQL literal.
+fn write_literal(f: &mut fmt::Formatter<'_>, value: &Value) -> fmt::Result {
+    match value {
+        Value::String(s) | Value::Bitmap(s) | Value::Variant(s) | Value::Geometry(s) => {
+            write_quoted(f, s)
+        }
+        // Dates and timestamps render without special characters but still need to be in quotes
+        Value::Timestamp(_) | Value::Date(_) => write!(f, "'{}'", value),
+        // NaN and infinity have no literal form but databend parses them from strings
+        Value::Number(NumberValue::Float32(v)) if !v.is_finite() => write_quoted(f, &v.to_string()),
+        Value::Number(NumberValue::Float64(v)) if !v.is_finite() => write_quoted(f, &v.to_string()),
+        Value::Number(NumberValue::Decimal128(v, size)) => write_decimal(f, *v, size.scale),
+        Value::Array(values) => {
+            write!(f, "[")?;
+            write_list(f, values)?;
+            write!(f, "]")
+        }
+        Value::Tuple(values) => {
+            write!(f, "(")?;
+            write_list(f, values)?;
+            // Without the trailing comma `(1)` is just a parenthesised 1
+            if values.len() == 1 {
+                write!(f, ",")?;
+            }
+            write!(f, ")")
+        }
+        Value::Map(entries) => {
+            write!(f, "{{")?;
+            let mut entries = entries.iter().peekable();
+            while let Some((key, value)) = entries.next() {
+                write_literal(f, key)?;
+                write!(f, ": ")?;
+                write_literal(f, value)?;
+                if entries.peek().is_some() {
+                    write!(f, ", ")?;
+                }
+            }
+            write!(f, "}}")
+        }
+        _ => write!(f, "{}", value),
+    }
+}
+
+fn write_list(f: &mut fmt::Formatter<'_>, values: &[Value]) -> fmt::Result {
+    let mut values = values.iter().peekable();
+    while let Some(value) = values.next() {
+        write_literal(f, value)?;
+        if values.peek().is_some() {
+            write!(f, ", ")?;
+        }
+    }
+    Ok(())
+}
+
+/// Write a string in single quotes, escaping anything databend would otherwise interpret.
+/// Databend treats a backslash in a string literal as an escape character, so backslashes
+/// need escaping along with the quotes themselves.
+fn write_quoted(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    use fmt::Write;
+    f.write_char('\'')?;
+    for c in s.chars() {
+        match c {
+            '\'' => f.write_str("\\'")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            '\0' => f.write_str("\\0")?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('\'')
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use rust_decimal::Decimal;
+
+    use super::*;
+    use crate::dialect::StringLiteral;
+    use crate::row::ToValue;
+
+    /// `s` as a literal, through both the [Value] and the borrowed string paths.
+    fn literal(s: &str) -> String {
+        let value = InsertValue::from(s);
+        let rendered = value.literal(&Databend).to_string();
+        let borrowed = StringLiteral {
+            dialect: &Databend,
+            value: s,
+        };
+        assert_eq!(borrowed.to_string(), rendered);
+        rendered
+    }
+
+    /// `value` converted as a column value and written as a literal.
+    fn value_literal(value: impl ToValue) -> String {
+        value.to_value().unwrap().literal(&Databend).to_string()
+    }
+
+    #[test]
+    fn escapes_quotes() {
+        assert_eq!(literal("St Mary's"), r"'St Mary\'s'");
+    }
+
+    #[test]
+    fn escapes_backslashes() {
+        assert_eq!(literal(r"a\b"), r"'a\\b'");
+    }
+
+    #[test]
+    fn escapes_newlines() {
+        assert_eq!(literal("line\nbreak"), r"'line\nbreak'");
+    }
+
+    #[test]
+    fn quotes_cannot_end_the_literal() {
+        assert_eq!(literal("'; DROP TABLE x; --"), r"'\'; DROP TABLE x; --'");
+    }
+
+    #[test]
+    fn keeps_multi_byte_text() {
+        assert_eq!(
+            literal("Marylebone Road – 東京 🌫"),
+            "'Marylebone Road – 東京 🌫'"
+        );
+    }
+
+    #[test]
+    fn decimals_keep_their_scale() {
+        assert_eq!(value_literal(Decimal::new(12340, 3)), "12.340");
+        assert_eq!(value_literal(Decimal::new(-5, 2)), "-0.05");
+        assert_eq!(value_literal(Decimal::new(7, 0)), "7");
+    }
+
+    #[test]
+    fn dates_are_stored_as_days() {
+        let date = jiff::civil::date(2024, 1, 2);
+        assert_eq!(date.to_value().unwrap().0, Value::Date(19724));
+        assert_eq!(value_literal(date), "'2024-01-02'");
+        assert_eq!(
+            value_literal(jiff::civil::date(1969, 12, 31)),
+            "'1969-12-31'"
+        );
+    }
+
+    #[test]
+    fn floats() {
+        assert_eq!(value_literal(1.5_f32), "1.5");
+        assert_eq!(value_literal(-0.25_f64), "-0.25");
+    }
+
+    #[test]
+    fn non_finite_floats_are_quoted() {
+        assert_eq!(value_literal(f64::NAN), "'NaN'");
+        assert_eq!(value_literal(f64::INFINITY), "'inf'");
+        assert_eq!(value_literal(f64::NEG_INFINITY), "'-inf'");
+        assert_eq!(value_literal(f32::NAN), "'NaN'");
+        assert_eq!(value_literal(f32::INFINITY), "'inf'");
+    }
+
+    #[test]
+    fn nested_arrays_and_maps() {
+        assert_eq!(value_literal(vec![vec![1_i64, 2], vec![]]), "[[1, 2], []]");
+        assert_eq!(value_literal(vec![vec!["St Mary's"]]), r"[['St Mary\'s']]");
+        let map = BTreeMap::from([("NO2", vec![1.5_f64]), ("PM10", vec![])]);
+        assert_eq!(value_literal(map), "{'NO2': [1.5], 'PM10': []}");
+        let nested =
+            BTreeMap::from([("MY1", BTreeMap::from([("NO2", Some(1_i64)), ("O3", None)]))]);
+        assert_eq!(value_literal(nested), "{'MY1': {'NO2': 1, 'O3': NULL}}");
+    }
+
+    #[test]
+    fn datetimes_are_written_in_utc() {
+        let datetime = jiff::civil::datetime(2024, 1, 2, 3, 4, 5, 123_456_000);
+        assert_eq!(value_literal(datetime), "'2024-01-02 03:04:05.123456'");
+        let zoned = datetime
+            .to_zoned(jiff::tz::TimeZone::fixed(jiff::tz::offset(1)))
+            .unwrap();
+        assert_eq!(value_literal(&zoned), "'2024-01-02 02:04:05.123456'");
+        assert_eq!(
+            value_literal(zoned.timestamp()),
+            "'2024-01-02 02:04:05.123456'"
+        );
+    }
+}
@@ -0,0 +1,180 @@
+//! The SQL dialects tables can be written in. An [crate::Executor] picks the dialect used
+//! to render statements for it, so the same [crate::Table] types can be stored in databend
+//! or in a local SQLite file.
+
+mod databend;
+mod sqlite;
+
+use std::fmt::{self, Write};
+
+use databend_driver::Value;
+
+use crate::error::{Error, Result};
+use crate::tables::{Field, TableOptions};
+use crate::types::ColumnType;
+
+pub use databend::Databend;
+pub use sqlite::Sqlite;
+
+/// How statements and values are written for a particular database.
+pub trait Dialect: Send + Sync {
+    /// Name of the database the dialect is for, such as `databend`.
+    fn name(&self) -> &'static str;
+
+    /// The name of `column_type` in a column definition.
+    fn column_type(&self, column_type: &ColumnType) -> String;
+
+    /// Write `value` as a SQL literal. Returns [fmt::Error] if the value has no literal in
+    /// the dialect, which is reported as [Error::Conversion].
+    fn write_literal(&self, f: &mut fmt::Formatter<'_>, value: &Value) -> fmt::Result;
+
+    /// Write `s` as a string literal. Dialects override this to write borrowed strings
+    /// without copying them into a [Value::String].
+    fn write_string(&self, f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+        self.write_literal(f, &Value::String(s.to_string()))
+    }
+
+    /// The start of a statement that inserts rows into `columns` of `table`, up to and
+    /// including `VALUES `.
+    ///
+    /// Here and below, `table` is written as returned by [Dialect::qualified_table].
+    fn insert_prefix(&self, table: &str, columns: &[&str]) -> String {
+        format!(
+            "INSERT INTO {} ({}) VALUES ",
+            table,
+            identifier_list(self, columns)
+        )
+    }
+
+    /// Like [Dialect::insert_prefix], but replacing existing rows with the same `keys`.
+    fn upsert_prefix(&self, table: &str, columns: &[&str], keys: &[&str]) -> String;
+
+    /// `name` as a quoted identifier, so reserved words like `value` can be used as names.
+    /// Quoted names are case sensitive. Defaults to standard SQL double quotes.
+    fn quote_identifier(&self, name: &str) -> String {
+        format!("\"{}\"", name.replace('"', "\"\""))
+    }
+
+    /// `table` as it is referred to in statements: quoted, and prefixed with its database if
+    /// it is not in the connection's current database.
+    fn qualified_table(&self, database: Option<&str>, table: &str) -> String {
+        match database {
+            Some(database) => format!(
+                "{}.{}",
+                self.quote_identifier(database),
+                self.quote_identifier(table)
+            ),
+            None => self.quote_identifier(table),
+        }
+    }
+
+    /// A statement creating `database` if it does not already exist, or `None` if the
+    /// dialect has no databases.
+    fn create_database(&self, database: &str) -> Option<String> {
+        Some(format!(
+            "CREATE DATABASE IF NOT EXISTS {};",
+            self.quote_identifier(database)
+        ))
+    }
+
+    /// A statement removing every row from `table`.
+    fn truncate_table(&self, table: &str) -> String {
+        format!("TRUNCATE TABLE {}", table)
+    }
+
+    /// A statement dropping `table` if it exists.
+    fn drop_table(&self, table: &str) -> String {
+        format!("DROP TABLE IF EXISTS {}", table)
+    }
+
+    /// A column as it appears in a `CREATE TABLE` or `ALTER TABLE` statement.
+    fn column_definition(&self, field: &Field) -> String {
+        let nullable = if field.nullable { "NULL" } else { "NOT NULL" };
+        format!(
+            "{} {} {}",
+            self.quote_identifier(field.name),
+            self.column_type(&field.data_type),
+            nullable
+        )
+    }
+
+    /// A statement creating `table` with `fields` if it does not already exist.
+    /// [TableOptions] are ignored unless the dialect overrides this.
+    fn create_table(&self, table: &str, fields: &[Field], _options: &TableOptions) -> String {
+        let columns: Vec<_> = fields
+            .iter()
+            .map(|field| self.column_definition(field))
+            .collect();
+        format!(
+            "CREATE TABLE IF NOT EXISTS {} ({});",
+            table,
+            columns.join(", ")
+        )
+    }
+}
+
+/// Quoted names separated by commas.
+pub(crate) fn identifier_list<D: Dialect + ?Sized>(dialect: &D, names: &[&str]) -> String {
+    let names: Vec<_> = names
+        .iter()
+        .map(|name| dialect.quote_identifier(name))
+        .collect();
+    names.join(", ")
+}
+
+/// Displays a value as a literal of a [Dialect].
+pub(crate) struct Literal<'a> {
+    pub(crate) dialect: &'a dyn Dialect,
+    pub(crate) value: &'a Value,
+}
+
+impl Literal<'_> {
+    /// Append the literal to `sql`. Fails if the dialect cannot write the value, such as a
+    /// timestamp outside the range SQLite's dates can hold.
+    pub(crate) fn write_to(&self, sql: &mut String) -> Result<()> {
+        write!(sql, "{}", self).map_err(|_| {
+            Error::Conversion(format!("Cannot write {:?} as a SQL literal", self.value))
+        })
+    }
+}
+
+impl fmt::Display for Literal<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.dialect.write_literal(f, self.value)
+    }
+}
+
+/// Displays a string as a string literal of a [Dialect].
+pub(crate) struct StringLiteral<'a> {
+    pub(crate) dialect: &'a dyn Dialect,
+    pub(crate) value: &'a str,
+}
+
+impl StringLiteral<'_> {
+    /// Append the literal to `sql`, like [Literal::write_to].
+    pub(crate) fn write_to(&self, sql: &mut String) -> Result<()> {
+        write!(sql, "{}", self).map_err(|_| {
+            Error::Conversion(format!("Cannot write {:?} as a SQL literal", self.value))
+        })
+    }
+}
+
+impl fmt::Display for StringLiteral<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.dialect.write_string(f, self.value)
+    }
+}
+
+/// Write an unscaled decimal with `scale` digits after the point, e.g. `12345, 2` as `123.45`.
+fn write_decimal(f: &mut fmt::Formatter<'_>, value: i128, scale: u8) -> fmt::Result {
+    let digits = value.unsigned_abs().to_string();
+    let scale = scale as usize;
+    let sign = if value < 0 { "-" } else { "" };
+    if scale == 0 {
+        return write!(f, "{}{}", sign, digits);
+    }
+    // Pad so there is always at least one digit before the point
+    let digits = format!("{:0>width$}", digits, width = scale + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - scale);
+    write!(f, "{}{}.{}", sign, whole, fraction)
+}
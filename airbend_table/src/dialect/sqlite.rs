@@ -0,0 +1,274 @@
+use std::fmt;
+
+use databend_driver::{NumberValue, Value};
+
+use super::{identifier_list, write_decimal, Dialect};
+use crate::load::to_json;
+use crate::select::FromValue;
+use crate::tables::{Field, TableOptions};
+use crate::types::ColumnType;
+
+/// SQL understood by SQLite.
+///
+/// Column types keep their databend names wherever SQLite derives the right affinity from
+/// them, so values can be converted back when they are read. Dates and timestamps are stored
+/// as UTC text that sorts in time order, and `ARRAY`, `MAP` and `TUPLE` values as JSON text.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sqlite;
+
+impl Dialect for Sqlite {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    fn column_type(&self, column_type: &ColumnType) -> String {
+        match column_type {
+            ColumnType::String | ColumnType::Bitmap | ColumnType::Geometry => "TEXT".to_string(),
+            ColumnType::Binary => "BLOB".to_string(),
+            ColumnType::Nullable(inner) => self.column_type(inner),
+            ColumnType::Array(_) | ColumnType::Map(..) | ColumnType::Tuple(_) => "JSON".to_string(),
+            column_type => column_type.to_string(),
+        }
+    }
+
+    fn write_literal(&self, f: &mut fmt::Formatter<'_>, value: &Value) -> fmt::Result {
+        match value {
+            Value::Null => write!(f, "NULL"),
+            Value::Boolean(v) => write!(f, "{}", u8::from(*v)),
+            Value::Number(NumberValue::Float32(v)) => write_float(f, (*v).into()),
+            Value::Number(NumberValue::Float64(v)) => write_float(f, *v),
+            Value::Number(NumberValue::Decimal128(v, size)) => write_decimal(f, *v, size.scale),
+            Value::Number(_) => write!(f, "{}", value),
+            Value::String(s) | Value::Bitmap(s) | Value::Variant(s) | Value::Geometry(s) => {
+                write_quoted(f, s)
+            }
+            Value::Binary(bytes) => {
+                write!(f, "X'")?;
+                for byte in bytes {
+                    write!(f, "{:02X}", byte)?;
+                }
+                write!(f, "'")
+            }
+            Value::Timestamp(_) => {
+                let timestamp = jiff::Timestamp::from_value(value).map_err(|_| fmt::Error)?;
+                write_quoted(f, &timestamp_text(timestamp))
+            }
+            Value::Date(_) => {
+                let date = jiff::civil::Date::from_value(value).map_err(|_| fmt::Error)?;
+                write_quoted(f, &date.to_string())
+            }
+            value => {
+                let json = to_json(value).map_err(|_| fmt::Error)?;
+                write_quoted(f, &json.to_string())
+            }
+        }
+    }
+
+    fn write_string(&self, f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+        write_quoted(f, s)
+    }
+
+    fn upsert_prefix(&self, table: &str, columns: &[&str], _keys: &[&str]) -> String {
+        // Rows are matched on the UNIQUE constraint added by create_table
+        format!(
+            "INSERT OR REPLACE INTO {} ({}) VALUES ",
+            table,
+            identifier_list(self, columns)
+        )
+    }
+
+    /// A SQLite file is a single database, so tables are never qualified by one.
+    fn qualified_table(&self, _database: Option<&str>, table: &str) -> String {
+        self.quote_identifier(table)
+    }
+
+    fn create_database(&self, _database: &str) -> Option<String> {
+        None
+    }
+
+    fn truncate_table(&self, table: &str) -> String {
+        // SQLite has no TRUNCATE, but optimises an unfiltered DELETE the same way
+        format!("DELETE FROM {}", table)
+    }
+
+    /// SQLite has no cluster keys, comments or table options, so [TableOptions] are ignored.
+    fn create_table(&self, table: &str, fields: &[Field], _options: &TableOptions) -> String {
+        let mut columns: Vec<_> = fields
+            .iter()
+            .map(|field| self.column_definition(field))
+            .collect();
+        let keys: Vec<_> = fields
+            .iter()
+            .filter(|field| field.key)
+            .map(|field| field.name)
+            .collect();
+        if !keys.is_empty() {
+            columns.push(format!("UNIQUE ({})", identifier_list(self, &keys)));
+        }
+        format!(
+            "CREATE TABLE IF NOT EXISTS {} ({});",
+            table,
+            columns.join(", ")
+        )
+    }
+}
+
+/// SQLite has no NaN and stores it as `NULL`. Infinities are written as out of range numbers.
+fn write_float(f: &mut fmt::Formatter<'_>, value: f64) -> fmt::Result {
+    if value.is_nan() {
+        write!(f, "NULL")
+    } else if value.is_infinite() {
+        let sign = if value < 0.0 { "-" } else { "" };
+        write!(f, "{}9e999", sign)
+    } else {
+        write!(f, "{:?}", value)
+    }
+}
+
+/// A timestamp as `YYYY-MM-DD HH:MM:SS.ffffff` in UTC. The fixed width keeps text
+/// comparisons in time order.
+fn timestamp_text(timestamp: jiff::Timestamp) -> String {
+    let datetime = jiff::tz::Offset::UTC.to_datetime(timestamp);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:06}",
+        datetime.year(),
+        datetime.month(),
+        datetime.day(),
+        datetime.hour(),
+        datetime.minute(),
+        datetime.second(),
+        datetime.subsec_nanosecond() / 1000
+    )
+}
+
+/// SQLite strings only need their quotes doubled.
+fn write_quoted(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    use fmt::Write;
+    f.write_char('\'')?;
+    for c in s.chars() {
+        if c == '\'' {
+            f.write_char('\'')?;
+        }
+        f.write_char(c)?;
+    }
+    f.write_char('\'')
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use rust_decimal::Decimal;
+
+    use super::*;
+    use crate::dialect::StringLiteral;
+    use crate::row::ToValue;
+    use crate::tables::InsertValue;
+
+    /// `s` as a literal, through both the [Value] and the borrowed string paths.
+    fn literal(s: &str) -> String {
+        let value = InsertValue::from(s);
+        let rendered = value.literal(&Sqlite).to_string();
+        let borrowed = StringLiteral {
+            dialect: &Sqlite,
+            value: s,
+        };
+        assert_eq!(borrowed.to_string(), rendered);
+        rendered
+    }
+
+    /// `value` converted as a column value and written as a literal.
+    fn value_literal(value: impl ToValue) -> String {
+        value.to_value().unwrap().literal(&Sqlite).to_string()
+    }
+
+    #[test]
+    fn doubles_quotes() {
+        assert_eq!(literal("St Mary's"), "'St Mary''s'");
+    }
+
+    #[test]
+    fn keeps_backslashes() {
+        assert_eq!(literal(r"a\b"), r"'a\b'");
+    }
+
+    #[test]
+    fn keeps_newlines() {
+        assert_eq!(literal("line\nbreak"), "'line\nbreak'");
+    }
+
+    #[test]
+    fn quotes_cannot_end_the_literal() {
+        assert_eq!(literal("'; DROP TABLE x; --"), "'''; DROP TABLE x; --'");
+    }
+
+    #[test]
+    fn keeps_multi_byte_text() {
+        assert_eq!(
+            literal("Marylebone Road – 東京 🌫"),
+            "'Marylebone Road – 東京 🌫'"
+        );
+    }
+
+    #[test]
+    fn decimals_keep_their_scale() {
+        assert_eq!(value_literal(Decimal::new(12340, 3)), "12.340");
+        assert_eq!(value_literal(Decimal::new(-5, 2)), "-0.05");
+        assert_eq!(value_literal(Decimal::new(7, 0)), "7");
+    }
+
+    #[test]
+    fn dates_are_stored_as_days() {
+        let date = jiff::civil::date(2024, 1, 2);
+        assert_eq!(date.to_value().unwrap().0, Value::Date(19724));
+        assert_eq!(value_literal(date), "'2024-01-02'");
+        assert_eq!(
+            value_literal(jiff::civil::date(1969, 12, 31)),
+            "'1969-12-31'"
+        );
+    }
+
+    #[test]
+    fn floats() {
+        assert_eq!(value_literal(1.5_f32), "1.5");
+        assert_eq!(value_literal(-0.25_f64), "-0.25");
+        assert_eq!(value_literal(2.0_f64), "2.0");
+    }
+
+    #[test]
+    fn non_finite_floats() {
+        assert_eq!(value_literal(f64::NAN), "NULL");
+        assert_eq!(value_literal(f64::INFINITY), "9e999");
+        assert_eq!(value_literal(f64::NEG_INFINITY), "-9e999");
+        assert_eq!(value_literal(f32::NAN), "NULL");
+        assert_eq!(value_literal(f32::INFINITY), "9e999");
+    }
+
+    #[test]
+    fn nested_arrays_and_maps_are_json() {
+        assert_eq!(value_literal(vec![vec![1_i64, 2], vec![]]), "'[[1,2],[]]'");
+        assert_eq!(
+            value_literal(vec![vec!["St Mary's"]]),
+            r#"'[["St Mary''s"]]'"#
+        );
+        let map = BTreeMap::from([("NO2", vec![1.5_f64]), ("PM10", vec![])]);
+        assert_eq!(value_literal(map), r#"'{"NO2":[1.5],"PM10":[]}'"#);
+        let nested =
+            BTreeMap::from([("MY1", BTreeMap::from([("NO2", Some(1_i64)), ("O3", None)]))]);
+        assert_eq!(value_literal(nested), r#"'{"MY1":{"NO2":1,"O3":null}}'"#);
+    }
+
+    #[test]
+    fn datetimes_are_written_in_utc() {
+        let datetime = jiff::civil::datetime(2024, 1, 2, 3, 4, 5, 123_456_000);
+        assert_eq!(value_literal(datetime), "'2024-01-02 03:04:05.123456'");
+        let zoned = datetime
+            .to_zoned(jiff::tz::TimeZone::fixed(jiff::tz::offset(1)))
+            .unwrap();
+        assert_eq!(value_literal(&zoned), "'2024-01-02 02:04:05.123456'");
+        assert_eq!(
+            value_literal(zoned.timestamp()),
+            "'2024-01-02 02:04:05.123456'"
+        );
+    }
+}
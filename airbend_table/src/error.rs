@@ -0,0 +1,203 @@
+use std::fmt;
+
+/// Errors returned by this crate.
+///
+/// Separates failures to reach the database from statements the database rejected, so callers
+/// can decide what is worth retrying with [Error::is_transient].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The database could not be reached, or the connection failed while a statement was running.
+    Connection(String),
+    /// A statement could not be built, e.g. a filter on an unknown column or an upsert of a
+    /// table without key columns. Nothing was sent to the database.
+    Generation(String),
+    /// The database rejected a statement: a syntax error, a missing table, a conflicting schema...
+    Server {
+        /// The error code reported by the database, if it sent one.
+        code: Option<u16>,
+        message: String,
+    },
+    /// A value could not be converted between its Rust and SQL representations.
+    Conversion(String),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Databend error codes for failures that can succeed when the statement is run again.
+const TRANSIENT_SERVER_CODES: [u16; 1] = [
+    // TableVersionMismatched: another statement committed to the table first
+    2009,
+];
+
+impl Error {
+    /// Whether running the same statement again might succeed: connection failures, and
+    /// server errors caused by concurrent writes. Invalid statements and bad values are not.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Connection(_) => true,
+            Error::Server {
+                code: Some(code), ..
+            } => TRANSIENT_SERVER_CODES.contains(code),
+            _ => false,
+        }
+    }
+
+    /// The error code reported by the database, for [Error::Server] errors that have one.
+    pub fn code(&self) -> Option<u16> {
+        match self {
+            Error::Server { code, .. } => *code,
+            _ => None,
+        }
+    }
+
+    /// The same error with `context` in front of its message.
+    pub(crate) fn context(self, context: impl fmt::Display) -> Self {
+        let prefix = |message: String| format!("{}: {}", context, message);
+        match self {
+            Error::Connection(message) => Error::Connection(prefix(message)),
+            Error::Generation(message) => Error::Generation(prefix(message)),
+            Error::Server { code, message } => Error::Server {
+                code,
+                message: prefix(message),
+            },
+            Error::Conversion(message) => Error::Conversion(prefix(message)),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Connection(message) => write!(f, "Connection error: {}", message),
+            Error::Generation(message) => write!(f, "Could not generate SQL: {}", message),
+            Error::Server {
+                code: Some(code),
+                message,
+            } => write!(f, "Server error {}: {}", code, message),
+            Error::Server {
+                code: None,
+                message,
+            } => write!(f, "Server error: {}", message),
+            Error::Conversion(message) => write!(f, "Conversion error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<databend_driver::Error> for Error {
+    fn from(error: databend_driver::Error) -> Self {
+        use databend_client::error::Error as Api;
+        use databend_driver::Error as Driver;
+
+        let message = error.to_string();
+        match error {
+            Driver::Transport(_) | Driver::IO(_) => Error::Connection(message),
+            Driver::BadArgument(_) => Error::Generation(message),
+            Driver::Parsing(_) => Error::Conversion(message),
+            // The server's response to a query it rejected, with its error code
+            Driver::Api(Api::InvalidResponse(response)) => Error::Server {
+                code: Some(response.code),
+                message,
+            },
+            // The request never got a response, e.g. it was dropped
+            Driver::Api(Api::Request(_) | Api::IO(_)) => Error::Connection(message),
+            _ => Error::Server {
+                code: None,
+                message,
+            },
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for Error {
+    fn from(error: rusqlite::Error) -> Self {
+        use rusqlite::ErrorCode;
+
+        match &error {
+            rusqlite::Error::SqliteFailure(failure, _) => match failure.code {
+                // Another connection holds a lock on the database
+                ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked => {
+                    Error::Connection(error.to_string())
+                }
+                _ => Error::Server {
+                    code: u16::try_from(failure.extended_code).ok(),
+                    message: error.to_string(),
+                },
+            },
+            rusqlite::Error::FromSqlConversionFailure(..)
+            | rusqlite::Error::IntegralValueOutOfRange(..)
+            | rusqlite::Error::Utf8Error(_)
+            | rusqlite::Error::InvalidColumnType(..) => Error::Conversion(error.to_string()),
+            _ => Error::Server {
+                code: None,
+                message: error.to_string(),
+            },
+        }
+    }
+}
+
+/// Parse failures while converting values.
+macro_rules! conversion_errors {
+    ($($t:ty),*) => {
+        $(
+            impl From<$t> for Error {
+                fn from(error: $t) -> Self {
+                    Error::Conversion(error.to_string())
+                }
+            }
+        )*
+    };
+}
+
+conversion_errors!(
+    std::num::ParseIntError,
+    std::num::ParseFloatError,
+    std::string::FromUtf8Error,
+    jiff::Error,
+    rust_decimal::Error,
+    serde_json::Error
+);
+
+#[cfg(test)]
+mod tests {
+    use databend_client::error::Error as Api;
+    use databend_client::response::QueryError;
+    use databend_driver::Error as Driver;
+
+    use super::*;
+
+    fn rejected(code: u16, message: &str) -> Driver {
+        Driver::Api(Api::InvalidResponse(QueryError {
+            code,
+            message: message.to_string(),
+            detail: None,
+        }))
+    }
+
+    #[test]
+    fn reads_the_code_of_a_rejected_query() {
+        let error = Error::from(rejected(1025, "Unknown table `t` [2009] in query"));
+        assert_eq!(error.code(), Some(1025));
+        assert!(!error.is_transient());
+
+        let error = Error::from(rejected(2009, "Table version mismatched"));
+        assert_eq!(error.code(), Some(2009));
+        assert!(error.is_transient());
+    }
+
+    #[test]
+    fn api_errors_without_a_code() {
+        let error = Error::from(Driver::Api(Api::Request("connection reset".to_string())));
+        assert!(matches!(error, Error::Connection(_)));
+        assert!(error.is_transient());
+
+        // A message that looks like it has a code is not read as one
+        let message = "bad page [1234]".to_string();
+        let error = Error::from(Driver::Api(Api::SessionTimeout(message)));
+        assert!(matches!(error, Error::Server { code: None, .. }));
+        assert!(!error.is_transient());
+    }
+}
@@ -0,0 +1,134 @@
+use std::io::Cursor;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use databend_driver::{Connection, RowWithStats, ServerStats, Value};
+use futures::StreamExt;
+
+use crate::dialect::{Databend, Dialect};
+use crate::error::Result;
+use crate::load::LoadFormat;
+use crate::namespace::{Namespace, DEFAULT_NAMESPACE};
+
+/// What a statement wrote, as reported by the database. Returned by [crate::Query::execute]
+/// and the other builders that insert rows.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WriteStats {
+    /// Rows written by the statement.
+    pub rows: u64,
+    /// Bytes written by the server. 0 if the database does not report it.
+    pub bytes: u64,
+    /// How long the statement ran: the server's running time where it reports one,
+    /// otherwise the time until the client had its result.
+    pub elapsed: Duration,
+}
+
+impl WriteStats {
+    /// Add the counts and running time of another statement, such as the next batch of an
+    /// insert.
+    pub fn add(&mut self, other: &WriteStats) {
+        self.rows += other.rows;
+        self.bytes += other.bytes;
+        self.elapsed += other.elapsed;
+    }
+}
+
+impl From<ServerStats> for WriteStats {
+    fn from(stats: ServerStats) -> Self {
+        WriteStats {
+            rows: stats.write_rows as u64,
+            bytes: stats.write_bytes as u64,
+            elapsed: Duration::from_secs_f64(stats.running_time_ms.max(0.0) / 1000.0),
+        }
+    }
+}
+
+/// Runs the SQL generated by this crate.
+///
+/// Implemented for databend's [Connection], and by [crate::mock::MockConnection] so table
+/// code can be exercised without a server.
+#[async_trait::async_trait]
+pub trait Executor: Send + Sync {
+    /// Run a statement, returning the number of rows affected.
+    async fn exec(&self, sql: &str) -> Result<i64>;
+
+    /// Run a statement that writes rows and report what it wrote. The default runs
+    /// [Executor::exec] and times it, so reports no bytes.
+    async fn write(&self, sql: &str) -> Result<WriteStats> {
+        let start = Instant::now();
+        let rows = self.exec(sql).await?;
+        Ok(WriteStats {
+            rows: rows.max(0) as u64,
+            bytes: 0,
+            elapsed: start.elapsed(),
+        })
+    }
+
+    /// Run a query and return the values of every row.
+    async fn query(&self, sql: &str) -> Result<Vec<Vec<Value>>>;
+
+    /// Run `sql` with `data` attached as a staged file, as used by [crate::BulkLoad]. The
+    /// file is shared rather than copied, so a load can be retried without cloning it.
+    async fn load(&self, sql: &str, format: LoadFormat, data: Arc<[u8]>) -> Result<WriteStats>;
+
+    /// The SQL dialect statements are rendered in before they are run.
+    fn dialect(&self) -> &dyn Dialect {
+        &Databend
+    }
+
+    /// The [Namespace] tables are created and looked up in. Only [crate::Namespaced]
+    /// connections have one; others use each table's own name and database.
+    fn namespace(&self) -> &Namespace {
+        &DEFAULT_NAMESPACE
+    }
+}
+
+#[async_trait::async_trait]
+impl Executor for dyn Connection {
+    async fn exec(&self, sql: &str) -> Result<i64> {
+        Ok(Connection::exec(self, sql).await?)
+    }
+
+    /// Reads the statement's progress, which databend sends as it runs. The last report
+    /// covers the whole statement.
+    async fn write(&self, sql: &str) -> Result<WriteStats> {
+        let start = Instant::now();
+        let mut rows = self.query_iter_ext(sql).await?;
+        let mut stats = None;
+        while let Some(row) = rows.next().await {
+            if let RowWithStats::Stats(progress) = row? {
+                stats = Some(progress);
+            }
+        }
+        Ok(match stats {
+            Some(stats) => stats.into(),
+            None => WriteStats {
+                elapsed: start.elapsed(),
+                ..WriteStats::default()
+            },
+        })
+    }
+
+    async fn query(&self, sql: &str) -> Result<Vec<Vec<Value>>> {
+        let mut rows = self.query_iter(sql).await?;
+        let mut results = vec![];
+        while let Some(row) = rows.next().await {
+            results.push(row?.values().to_vec());
+        }
+        Ok(results)
+    }
+
+    async fn load(&self, sql: &str, format: LoadFormat, data: Arc<[u8]>) -> Result<WriteStats> {
+        let size = data.len() as u64;
+        let stats = self
+            .load_data(
+                sql,
+                Box::new(Cursor::new(data)),
+                size,
+                Some(format.file_format_options()),
+                None,
+            )
+            .await?;
+        Ok(stats.into())
+    }
+}
@@ -0,0 +1,266 @@
+use databend_driver::Value;
+
+use crate::dialect::Dialect;
+use crate::error::{Error, Result};
+use crate::tables::{InsertValue, Table};
+
+/// A `WHERE` clause built up from simple conditions, all joined with `AND`.
+/// Column names are checked against the table schema as conditions are added.
+pub(crate) struct Filter {
+    conditions: Vec<Condition>,
+    unknown_columns: Vec<String>,
+    /// Columns compared with NULL by an operator other than `=` or `<>`, which would never
+    /// match.
+    null_comparisons: Vec<String>,
+}
+
+enum Condition {
+    Compare {
+        column: String,
+        op: &'static str,
+        value: InsertValue,
+    },
+    Between {
+        column: String,
+        low: InsertValue,
+        high: InsertValue,
+    },
+    IsNull {
+        column: String,
+        negated: bool,
+    },
+}
+
+impl Filter {
+    pub(crate) fn new() -> Self {
+        Self {
+            conditions: vec![],
+            unknown_columns: vec![],
+            null_comparisons: vec![],
+        }
+    }
+
+    /// Record `column` as unknown if it is not part of the schema for `T`.
+    pub(crate) fn check_column<T: Table>(&mut self, column: &str) {
+        if !T::schema().iter().any(|field| field.name == column) {
+            self.unknown_columns.push(column.to_string());
+        }
+    }
+
+    /// Compare `column` with `value`. Comparing with NULL using `=` or `<>` becomes
+    /// `IS NULL` or `IS NOT NULL`, since `= NULL` matches nothing; other operators are
+    /// reported by [Filter::validate].
+    pub(crate) fn compare<T: Table>(&mut self, column: &str, op: &'static str, value: InsertValue) {
+        if value.0 == Value::Null {
+            match op {
+                "=" => return self.is_null::<T>(column, false),
+                "<>" => return self.is_null::<T>(column, true),
+                _ => self.null_comparisons.push(column.to_string()),
+            }
+        }
+        self.check_column::<T>(column);
+        self.conditions.push(Condition::Compare {
+            column: column.to_string(),
+            op,
+            value,
+        });
+    }
+
+    pub(crate) fn between<T: Table>(&mut self, column: &str, low: InsertValue, high: InsertValue) {
+        if low.0 == Value::Null || high.0 == Value::Null {
+            self.null_comparisons.push(column.to_string());
+        }
+        self.check_column::<T>(column);
+        self.conditions.push(Condition::Between {
+            column: column.to_string(),
+            low,
+            high,
+        });
+    }
+
+    pub(crate) fn is_null<T: Table>(&mut self, column: &str, negated: bool) {
+        self.check_column::<T>(column);
+        self.conditions.push(Condition::IsNull {
+            column: column.to_string(),
+            negated,
+        });
+    }
+
+    /// Fails if any condition (or other clause checked with [Filter::check_column])
+    /// referenced a column that does not exist, or ordered a column against NULL.
+    pub(crate) fn validate<T: Table>(&self) -> Result<()> {
+        if !self.unknown_columns.is_empty() {
+            return Err(Error::Generation(format!(
+                "Unknown column(s) for table {}: {}",
+                T::name(),
+                self.unknown_columns.join(", ")
+            )));
+        }
+        if !self.null_comparisons.is_empty() {
+            return Err(Error::Generation(format!(
+                "Cannot compare column(s) of table {} with NULL: {}. Use where_null or where_not_null",
+                T::name(),
+                self.null_comparisons.join(", ")
+            )));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.conditions.is_empty()
+    }
+
+    /// Append ` WHERE ...` to `sql`, with values written as literals of `dialect`.
+    /// Writes nothing if there are no conditions. Fails if a value has no literal in
+    /// `dialect`.
+    pub(crate) fn write_where(&self, sql: &mut String, dialect: &dyn Dialect) -> Result<()> {
+        let mut conditions = self.conditions.iter().peekable();
+        if conditions.peek().is_some() {
+            sql.push_str(" WHERE ");
+        }
+        while let Some(condition) = conditions.next() {
+            let column = |column: &str| dialect.quote_identifier(column);
+            match condition {
+                Condition::Compare {
+                    column: c,
+                    op,
+                    value,
+                } => {
+                    sql.push_str(&format!("{} {} ", column(c), op));
+                    value.literal(dialect).write_to(sql)?;
+                }
+                Condition::Between {
+                    column: c,
+                    low,
+                    high,
+                } => {
+                    sql.push_str(&format!("{} BETWEEN ", column(c)));
+                    low.literal(dialect).write_to(sql)?;
+                    sql.push_str(" AND ");
+                    high.literal(dialect).write_to(sql)?;
+                }
+                Condition::IsNull { column: c, negated } => {
+                    let not = if *negated { "NOT " } else { "" };
+                    sql.push_str(&format!("{} IS {}NULL", column(c), not));
+                }
+            }
+            if conditions.peek().is_some() {
+                sql.push_str(" AND ");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The `where_*` methods of a query builder over a table `T`, for an `impl` block of a
+/// type with a `filter: Filter` field.
+macro_rules! where_methods {
+    () => {
+        /// Matches rows where `column` equals `value`. A NULL `value`, such as `None`,
+        /// matches rows where `column` is NULL.
+        pub fn where_eq(mut self, column: &str, value: impl Into<InsertValue>) -> Self {
+            self.filter.compare::<T>(column, "=", value.into());
+            self
+        }
+
+        /// Matches rows where `column` differs from `value`. A NULL `value`, such as
+        /// `None`, matches rows where `column` is not NULL.
+        pub fn where_ne(mut self, column: &str, value: impl Into<InsertValue>) -> Self {
+            self.filter.compare::<T>(column, "<>", value.into());
+            self
+        }
+
+        pub fn where_lt(mut self, column: &str, value: impl Into<InsertValue>) -> Self {
+            self.filter.compare::<T>(column, "<", value.into());
+            self
+        }
+
+        pub fn where_le(mut self, column: &str, value: impl Into<InsertValue>) -> Self {
+            self.filter.compare::<T>(column, "<=", value.into());
+            self
+        }
+
+        pub fn where_gt(mut self, column: &str, value: impl Into<InsertValue>) -> Self {
+            self.filter.compare::<T>(column, ">", value.into());
+            self
+        }
+
+        pub fn where_ge(mut self, column: &str, value: impl Into<InsertValue>) -> Self {
+            self.filter.compare::<T>(column, ">=", value.into());
+            self
+        }
+
+        /// Inclusive range filter. Neither bound can be NULL.
+        pub fn where_between(
+            mut self,
+            column: &str,
+            low: impl Into<InsertValue>,
+            high: impl Into<InsertValue>,
+        ) -> Self {
+            self.filter.between::<T>(column, low.into(), high.into());
+            self
+        }
+
+        pub fn where_null(mut self, column: &str) -> Self {
+            self.filter.is_null::<T>(column, false);
+            self
+        }
+
+        pub fn where_not_null(mut self, column: &str) -> Self {
+            self.filter.is_null::<T>(column, true);
+            self
+        }
+    };
+}
+
+pub(crate) use where_methods;
+
+#[cfg(test)]
+mod tests {
+    use crate::{delete, select, AirbendTable, Databend, Error, Namespace, Sqlite};
+
+    #[derive(AirbendTable)]
+    #[airbend_table(table_name = "reading")]
+    struct Reading {
+        site_code: String,
+        species_code: Option<String>,
+        value: Option<f64>,
+    }
+
+    #[test]
+    fn none_compares_with_is_null() {
+        let sql = select::<Reading>()
+            .where_eq("species_code", None::<String>)
+            .where_ne("value", None::<f64>)
+            .sql(&Databend, &Namespace::default())
+            .unwrap();
+        assert_eq!(
+            sql,
+            "SELECT `site_code`, `species_code`, `value` FROM `reading` \
+             WHERE `species_code` IS NULL AND `value` IS NOT NULL"
+        );
+
+        let sql = delete::<Reading>()
+            .where_eq("site_code", "MY1")
+            .where_eq("species_code", None::<String>)
+            .sql(&Sqlite, &Namespace::default())
+            .unwrap();
+        assert_eq!(
+            sql,
+            "DELETE FROM \"reading\" WHERE \"site_code\" = 'MY1' AND \"species_code\" IS NULL"
+        );
+    }
+
+    #[test]
+    fn rejects_ordering_against_null() {
+        let sql = select::<Reading>()
+            .where_gt("value", None::<f64>)
+            .sql(&Databend, &Namespace::default());
+        assert!(matches!(sql, Err(Error::Generation(_))));
+
+        let sql = delete::<Reading>()
+            .where_between("value", 1.0, None::<f64>)
+            .sql(&Databend, &Namespace::default());
+        assert!(matches!(sql, Err(Error::Generation(_))));
+    }
+}
@@ -1,8 +1,59 @@
+mod batch;
+pub mod dbt;
+mod delete;
+pub mod dialect;
+mod error;
+mod executor;
+mod filter;
+mod load;
+mod migrate;
+pub mod mock;
+mod namespace;
+pub mod registry;
+mod row;
+mod select;
+mod sink;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
 mod tables;
+mod transaction;
+pub mod types;
 
-pub use databend_driver::{Client, Connection, DataType};
+pub use databend_driver::{Client, Connection, DataType, Value};
 pub use databend_driver_core::schema::NumberDataType;
 
-pub use tables::{create, insert, Field, InsertValue, Table};
+pub use batch::{BatchInsert, BatchOptions, BatchOutcome};
+pub use delete::{delete, Delete};
+pub use dialect::{Databend, Dialect, Sqlite};
+pub use error::{Error, Result};
+pub use executor::{Executor, WriteStats};
+pub use load::{BulkLoad, LoadFormat};
+pub use migrate::{migrate, MigrateOptions, Migration, MigrationStep};
+pub use namespace::{Namespace, Namespaced};
+pub use registry::{create_all, migrate_all, TableDef};
+pub use row::{RowWriter, ToValue};
+pub use select::{select, FromRow, FromValue, Order, Select};
+pub use sink::{InsertSink, SinkOptions, SinkOutcome};
+pub use tables::{
+    create, drop_table, insert, truncate, upsert, Columns, Field, Insert, InsertValue, Query,
+    Table, TableOptions, Upsert,
+};
+pub use transaction::{transaction, Transaction};
+pub use types::ColumnType;
 
-pub use airbend_table_derive::AirbendTable;
+pub use airbend_table_derive::{AirbendColumns, AirbendTable, AirbendTuple};
+
+// Lets the derives, which name `airbend_table`, be used in this crate's tests
+#[cfg(test)]
+extern crate self as airbend_table;
+
+/// Used by code generated by the derive macro. Not part of the public API.
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::select::{flattened, from_column, from_variant, tuple_elements};
+    pub use crate::tables::{to_variant, tuple};
+    pub use crate::types::assert_column_value;
+    pub use crate::types::element_type;
+    pub use inventory;
+    pub use serde;
+}
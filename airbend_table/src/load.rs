@@ -0,0 +1,201 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use databend_driver::{NumberValue, Value};
+
+use crate::error::Result;
+use crate::executor::{Executor, WriteStats};
+use crate::select::FromValue;
+use crate::tables::{Insert, InsertValue, Statement, Table, Upsert};
+
+/// File format rows are serialized to for a [BulkLoad].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadFormat {
+    Csv,
+    Ndjson,
+}
+
+impl LoadFormat {
+    pub(crate) fn file_format_options(&self) -> BTreeMap<&'static str, &'static str> {
+        match self {
+            LoadFormat::Csv => BTreeMap::from([
+                ("type", "CSV"),
+                ("field_delimiter", ","),
+                ("record_delimiter", "\n"),
+                ("quote", "\""),
+                ("skip_header", "0"),
+            ]),
+            LoadFormat::Ndjson => BTreeMap::from([("type", "NDJSON")]),
+        }
+    }
+}
+
+/// Rows serialized to a file and loaded through a databend stage, rather than sent as
+/// SQL text. Meant for large numbers of rows; the `load` bench compares it with
+/// [Insert::values] against a server.
+/// Created with [Insert::load] or [Upsert::load].
+pub struct BulkLoad {
+    /// Statement the staged file is attached to.
+    statement: Statement,
+    format: LoadFormat,
+    /// Shared with each [Executor::load], so running the load again does not copy the file.
+    data: Arc<[u8]>,
+    rows: usize,
+}
+
+/// The rows are serialized straight away, so `values` can be a `Vec<T>` or a borrowed slice.
+impl<T: Table> Insert<T> {
+    pub fn load(self, values: impl AsRef<[T]>, format: LoadFormat) -> Result<BulkLoad> {
+        BulkLoad::new(Statement::insert::<T>(), values.as_ref(), format)
+    }
+}
+
+impl<T: Table> Upsert<T> {
+    pub fn load(self, values: impl AsRef<[T]>, format: LoadFormat) -> Result<BulkLoad> {
+        BulkLoad::new(Statement::upsert::<T>()?, values.as_ref(), format)
+    }
+}
+
+impl BulkLoad {
+    fn new<T: Table>(statement: Statement, values: &[T], format: LoadFormat) -> Result<Self> {
+        let columns: Vec<_> = T::schema().iter().map(|field| field.name).collect();
+        let rows = values.len();
+        let mut data = String::new();
+        for value in values {
+            let row = value.to_row()?;
+            match format {
+                LoadFormat::Csv => write_csv_row(&mut data, &row)?,
+                LoadFormat::Ndjson => write_ndjson_row(&mut data, &columns, &row)?,
+            }
+        }
+
+        Ok(BulkLoad {
+            statement,
+            format,
+            data: data.into_bytes().into(),
+            rows,
+        })
+    }
+
+    /// Number of rows in the file.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Size of the file sent to the server.
+    pub fn bytes(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Send the file and load it. Can be run again if it fails, e.g. after a
+    /// [transient](crate::Error::is_transient) error.
+    pub async fn execute(&self, conn: &(impl Executor + ?Sized)) -> Result<WriteStats> {
+        let sql = self.statement.prefix(conn.dialect(), conn.namespace());
+        conn.load(sql.trim_end(), self.format, Arc::clone(&self.data))
+            .await
+    }
+}
+
+fn write_csv_row(out: &mut String, row: &[InsertValue]) -> Result<()> {
+    for (i, value) in row.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_csv_field(out, &value.0)?;
+    }
+    out.push('\n');
+    Ok(())
+}
+
+fn write_csv_field(out: &mut String, value: &Value) -> Result<()> {
+    match value {
+        // Databend's default NULL_DISPLAY for CSV
+        Value::Null => out.push_str("\\N"),
+        Value::String(s) | Value::Bitmap(s) | Value::Variant(s) | Value::Geometry(s) => {
+            write_csv_quoted(out, s)
+        }
+        Value::Boolean(_) | Value::Number(_) => out.push_str(&scalar_text(value)),
+        Value::Timestamp(_) => out.push_str(&jiff::Timestamp::from_value(value)?.to_string()),
+        Value::Date(_) => out.push_str(&jiff::civil::Date::from_value(value)?.to_string()),
+        // Nested values use the same text form as SQL literals
+        v => write_csv_quoted(out, &InsertValue::from(v.clone()).to_string()),
+    }
+    Ok(())
+}
+
+/// Quote a CSV field, doubling any quotes inside it.
+fn write_csv_quoted(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        if c == '"' {
+            out.push('"');
+        }
+        out.push(c);
+    }
+    out.push('"');
+}
+
+/// Text for a number or boolean, without the quotes used for non-finite floats in SQL.
+fn scalar_text(value: &Value) -> String {
+    match value {
+        Value::Number(NumberValue::Float32(v)) => v.to_string(),
+        Value::Number(NumberValue::Float64(v)) => v.to_string(),
+        v => InsertValue::from(v.clone()).to_string(),
+    }
+}
+
+fn write_ndjson_row(out: &mut String, columns: &[&str], row: &[InsertValue]) -> Result<()> {
+    let mut object = serde_json::Map::new();
+    for (column, value) in columns.iter().zip(row) {
+        object.insert(column.to_string(), to_json(&value.0)?);
+    }
+    out.push_str(&serde_json::Value::Object(object).to_string());
+    out.push('\n');
+    Ok(())
+}
+
+pub(crate) fn to_json(value: &Value) -> Result<serde_json::Value> {
+    use serde_json::Value as Json;
+
+    let json = match value {
+        Value::Null => Json::Null,
+        Value::EmptyArray => Json::Array(vec![]),
+        Value::EmptyMap => Json::Object(serde_json::Map::new()),
+        Value::Boolean(v) => Json::Bool(*v),
+        Value::String(s) | Value::Bitmap(s) | Value::Geometry(s) => Json::String(s.clone()),
+        // Variants are stored as JSON already; anything that does not parse is sent as a string
+        Value::Variant(s) => serde_json::from_str(s).unwrap_or_else(|_| Json::String(s.clone())),
+        Value::Number(number) => match number {
+            NumberValue::Int8(v) => (*v).into(),
+            NumberValue::Int16(v) => (*v).into(),
+            NumberValue::Int32(v) => (*v).into(),
+            NumberValue::Int64(v) => (*v).into(),
+            NumberValue::UInt8(v) => (*v).into(),
+            NumberValue::UInt16(v) => (*v).into(),
+            NumberValue::UInt32(v) => (*v).into(),
+            NumberValue::UInt64(v) => (*v).into(),
+            // NaN, infinities and decimals have no exact JSON number, so send their text
+            NumberValue::Float32(v) if v.is_finite() => (*v).into(),
+            NumberValue::Float64(v) if v.is_finite() => (*v).into(),
+            _ => Json::String(scalar_text(value)),
+        },
+        Value::Timestamp(_) => Json::String(jiff::Timestamp::from_value(value)?.to_string()),
+        Value::Date(_) => Json::String(jiff::civil::Date::from_value(value)?.to_string()),
+        Value::Array(values) | Value::Tuple(values) => {
+            Json::Array(values.iter().map(to_json).collect::<Result<_>>()?)
+        }
+        Value::Map(entries) => {
+            let mut object = serde_json::Map::new();
+            for (key, value) in entries {
+                let key = match key {
+                    Value::String(s) => s.clone(),
+                    key => scalar_text(key),
+                };
+                object.insert(key, to_json(value)?);
+            }
+            Json::Object(object)
+        }
+        v => Json::String(v.to_string()),
+    };
+    Ok(json)
+}
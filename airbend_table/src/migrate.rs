@@ -0,0 +1,543 @@
+use crate::dialect::{Databend, Dialect};
+use crate::error::{Error, Result};
+use crate::executor::Executor;
+use crate::namespace::Namespace;
+use crate::registry::TableDef;
+use crate::select::from_column;
+use crate::tables::{Field, InsertValue, Table};
+
+/// Controls what [migrate] is allowed to do.
+#[derive(Debug, Clone, Default)]
+pub struct MigrateOptions {
+    dry_run: bool,
+    allow_destructive: bool,
+}
+
+impl MigrateOptions {
+    /// Work out the statements needed but don't run them. A dry run returns every step,
+    /// destructive ones included, so the plan can be reviewed before it is allowed.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Allow dropping columns and changing column types.
+    pub fn allow_destructive(mut self, allow_destructive: bool) -> Self {
+        self.allow_destructive = allow_destructive;
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MigrationStep {
+    pub sql: String,
+    /// True if the statement can lose data (dropping a column or changing its type).
+    /// Adding a column or making a `NOT NULL` column nullable is not destructive.
+    pub destructive: bool,
+}
+
+/// The statements needed to bring a table in line with [Table::schema].
+#[derive(Debug, Clone)]
+pub struct Migration {
+    pub steps: Vec<MigrationStep>,
+    /// Differences that were left alone, such as a nullable column the schema declares
+    /// `NOT NULL`.
+    pub warnings: Vec<String>,
+    /// False if this was a dry run.
+    pub applied: bool,
+}
+
+impl Migration {
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+}
+
+impl std::fmt::Display for Migration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for step in &self.steps {
+            writeln!(f, "{}", step.sql)?;
+        }
+        Ok(())
+    }
+}
+
+/// A column as currently defined in the database.
+struct LiveColumn {
+    name: String,
+    data_type: String,
+    nullable: bool,
+}
+
+/// Compare the live definition of `T`'s table with [Table::schema] and run the
+/// `CREATE TABLE` or `ALTER TABLE` statements needed to reconcile them.
+///
+/// Destructive changes are refused (and nothing is run) unless
+/// [MigrateOptions::allow_destructive] is set. A dry run is never refused: it returns the
+/// whole plan with destructive steps marked.
+///
+/// A nullable column that the schema declares `NOT NULL` is left nullable, with a warning
+/// in [Migration::warnings]: tightening it fails if the column holds NULLs, and loosening
+/// the schema later would then count as destructive. Its type is still changed if that
+/// differs too.
+///
+/// The live definition is read from databend's `system.columns`, so this only works
+/// against databend (or [crate::mock::MockConnection]), and returns [Error::Generation] for
+/// connections in any other dialect. The table's database is created along with the table if
+/// it does not exist.
+pub async fn migrate<T: Table>(
+    conn: &(impl Executor + ?Sized),
+    options: MigrateOptions,
+) -> Result<Migration> {
+    migrate_table(conn, &TableDef::of::<T>(), options).await
+}
+
+pub(crate) async fn migrate_table(
+    conn: &(impl Executor + ?Sized),
+    table: &TableDef,
+    options: MigrateOptions,
+) -> Result<Migration> {
+    if conn.dialect().name() != Databend.name() {
+        return Err(Error::Generation("migrate requires databend".to_string()));
+    }
+    let namespace = conn.namespace();
+    let live_columns = live_columns(
+        conn,
+        namespace.database_of(table),
+        &namespace.table_name(table),
+    )
+    .await?;
+    let (steps, warnings) = plan(table, namespace, &live_columns);
+
+    let destructive: Vec<_> = steps
+        .iter()
+        .filter(|step| step.destructive)
+        .map(|step| step.sql.as_str())
+        .collect();
+    if !destructive.is_empty() && !options.allow_destructive && !options.dry_run {
+        return Err(Error::Generation(format!(
+            "Refusing to run destructive changes to table {}:\n{}",
+            table.name(),
+            destructive.join("\n")
+        )));
+    }
+
+    if !options.dry_run {
+        for step in &steps {
+            conn.exec(&step.sql).await?;
+        }
+    }
+
+    Ok(Migration {
+        steps,
+        warnings,
+        applied: !options.dry_run,
+    })
+}
+
+async fn live_columns(
+    conn: &(impl Executor + ?Sized),
+    database: Option<&str>,
+    table: &str,
+) -> Result<Vec<LiveColumn>> {
+    let database = match database {
+        Some(database) => InsertValue::from(database).to_string(),
+        None => "currentDatabase()".to_string(),
+    };
+    let sql = format!(
+        "SELECT name, type, is_nullable FROM system.columns \
+         WHERE `database` = {} AND `table` = {}",
+        database,
+        InsertValue::from(table)
+    );
+    let mut columns = vec![];
+    for row in conn.query(&sql).await? {
+        let row = row.as_slice();
+        let is_nullable: String = from_column(row, 2, "is_nullable")?;
+        columns.push(LiveColumn {
+            name: from_column(row, 0, "name")?,
+            data_type: from_column(row, 1, "type")?,
+            nullable: is_nullable.eq_ignore_ascii_case("YES"),
+        });
+    }
+    Ok(columns)
+}
+
+fn plan(
+    table: &TableDef,
+    namespace: &Namespace,
+    live_columns: &[LiveColumn],
+) -> (Vec<MigrationStep>, Vec<String>) {
+    if live_columns.is_empty() {
+        let create_database = namespace
+            .database_of(table)
+            .and_then(|database| Databend.create_database(database));
+        let steps = create_database
+            .into_iter()
+            .chain([table.create_sql(&Databend, namespace)])
+            .map(|sql| MigrationStep {
+                sql,
+                destructive: false,
+            })
+            .collect();
+        return (steps, vec![]);
+    }
+
+    let schema = table.schema();
+    let table = namespace.table(&Databend, table);
+    let mut steps = vec![];
+    let mut warnings = vec![];
+
+    for field in &schema {
+        match live_columns.iter().find(|c| c.name == field.name) {
+            None => steps.push(MigrationStep {
+                sql: format!(
+                    "ALTER TABLE {} ADD COLUMN {};",
+                    table,
+                    Databend.column_definition(field)
+                ),
+                destructive: false,
+            }),
+            Some(live) => {
+                let same_type =
+                    normalize_type(&live.data_type) == normalize_type(&field.data_type.to_string());
+                let tightened = live.nullable && !field.nullable;
+                if tightened {
+                    warnings.push(format!(
+                        "Column {} of table {} is nullable but declared NOT NULL; left nullable",
+                        field.name, table
+                    ));
+                }
+                let loosened = !live.nullable && field.nullable;
+                if !same_type || loosened {
+                    // Keep a nullable column nullable when only its type changes
+                    let field = Field {
+                        name: field.name,
+                        data_type: field.data_type.clone(),
+                        nullable: field.nullable || live.nullable,
+                        key: field.key,
+                        comment: field.comment,
+                    };
+                    steps.push(MigrationStep {
+                        sql: format!(
+                            "ALTER TABLE {} MODIFY COLUMN {};",
+                            table,
+                            Databend.column_definition(&field)
+                        ),
+                        // Making a column nullable keeps every value it holds
+                        destructive: !same_type,
+                    })
+                }
+            }
+        }
+    }
+
+    for live in live_columns {
+        if !schema.iter().any(|field| field.name == live.name) {
+            steps.push(MigrationStep {
+                sql: format!(
+                    "ALTER TABLE {} DROP COLUMN {};",
+                    table,
+                    Databend.quote_identifier(&live.name)
+                ),
+                destructive: true,
+            });
+        }
+    }
+
+    (steps, warnings)
+}
+
+/// Map the different spellings databend accepts for a type onto the name it reports, so a
+/// type written by [crate::ColumnType] compares equal to the one read back from
+/// `system.columns`. Nested types are normalised too: `Array(Int64)` and `ARRAY(BIGINT)` are
+/// the same type. Nullability of the column itself is compared separately, so a top level
+/// `Nullable(..)` or `NULL` is dropped, while nested nullable types become `T NULL`.
+fn normalize_type(data_type: &str) -> String {
+    normalize_nested(data_type).0
+}
+
+/// The normalised type and whether it is nullable.
+fn normalize_nested(data_type: &str) -> (String, bool) {
+    let data_type = data_type.trim();
+    let upper = data_type.to_uppercase();
+    if let Some(inner) = upper.strip_suffix(" NOT NULL") {
+        return (normalize_nested(&data_type[..inner.len()]).0, false);
+    }
+    if let Some(inner) = upper.strip_suffix(" NULL") {
+        return (normalize_nested(&data_type[..inner.len()]).0, true);
+    }
+
+    let Some((name, args)) = data_type
+        .strip_suffix(')')
+        .and_then(|rest| rest.split_once('('))
+    else {
+        return (type_alias(&upper), false);
+    };
+    let name = name.trim().to_uppercase();
+    let args = split_arguments(args);
+    if name == "NULLABLE" && args.len() == 1 {
+        return (normalize_nested(args[0]).0, true);
+    }
+
+    let args: Vec<_> = args
+        .into_iter()
+        .map(|arg| {
+            let arg = arg.trim();
+            // Precision and scale of a DECIMAL
+            if arg.chars().all(|c| c.is_ascii_digit()) {
+                return arg.to_string();
+            }
+            match normalize_nested(arg) {
+                (arg, true) => format!("{} NULL", arg),
+                (arg, false) => arg,
+            }
+        })
+        .collect();
+    (format!("{}({})", type_alias(&name), args.join(", ")), false)
+}
+
+/// Split the arguments of a type on the commas that are not inside nested parentheses.
+fn split_arguments(args: &str) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in args.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&args[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&args[start..]);
+    parts
+}
+
+/// The name databend reports for an upper case type name.
+fn type_alias(name: &str) -> String {
+    let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+    let alias = match name.as_str() {
+        "STRING" | "TEXT" | "CHAR" | "VARCHAR" => "VARCHAR",
+        "BOOL" | "BOOLEAN" => "BOOLEAN",
+        "INT8" | "TINYINT" => "TINYINT",
+        "INT16" | "SMALLINT" => "SMALLINT",
+        "INT32" | "INTEGER" | "INT" => "INT",
+        "INT64" | "BIGINT" => "BIGINT",
+        "UINT8" | "TINYINT UNSIGNED" => "TINYINT UNSIGNED",
+        "UINT16" | "SMALLINT UNSIGNED" => "SMALLINT UNSIGNED",
+        "UINT32" | "INTEGER UNSIGNED" | "INT UNSIGNED" => "INT UNSIGNED",
+        "UINT64" | "BIGINT UNSIGNED" => "BIGINT UNSIGNED",
+        "FLOAT32" | "REAL" | "FLOAT" => "FLOAT",
+        "FLOAT64" | "DOUBLE PRECISION" | "DOUBLE" => "DOUBLE",
+        "DATETIME" | "TIMESTAMP" => "TIMESTAMP",
+        "JSON" | "VARIANT" => "VARIANT",
+        _ => return name,
+    };
+    alias.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockConnection;
+    use crate::types::ColumnType;
+
+    struct Reading;
+
+    impl Table for Reading {
+        fn name() -> &'static str {
+            "reading"
+        }
+
+        fn schema() -> Vec<Field> {
+            let field = |name, data_type, nullable| Field {
+                name,
+                data_type,
+                nullable,
+                key: false,
+                comment: None,
+            };
+            vec![
+                field("site", ColumnType::String, false),
+                field("value", ColumnType::Float64, true),
+                field(
+                    "values",
+                    ColumnType::Array(Box::new(ColumnType::Nullable(Box::new(ColumnType::Int64)))),
+                    false,
+                ),
+                field(
+                    "ratio",
+                    ColumnType::Decimal {
+                        precision: 38,
+                        scale: 10,
+                    },
+                    true,
+                ),
+            ]
+        }
+
+        fn to_row(&self) -> Result<Vec<InsertValue>> {
+            Ok(vec![])
+        }
+    }
+
+    #[test]
+    fn normalizes_types_as_desc_reports_them() {
+        let same = [
+            ("VARCHAR", "String"),
+            ("Nullable(String)", "VARCHAR"),
+            ("VARCHAR NULL", "String"),
+            ("ARRAY(BIGINT)", "Array(Int64)"),
+            ("ARRAY(BIGINT NULL)", "Array(Nullable(Int64))"),
+            (
+                "Nullable(Array(Nullable(Int64)))",
+                "ARRAY(NULLABLE(BIGINT))",
+            ),
+            ("DECIMAL(38, 10)", "Decimal(38,10)"),
+            ("Nullable(Decimal(38, 10))", "DECIMAL(38,10) NULL"),
+            ("MAP(VARCHAR, DOUBLE)", "Map(String, Float64)"),
+            ("TUPLE(INT, VARCHAR NULL)", "Tuple(Int32, Nullable(String))"),
+            ("TIMESTAMP", "DateTime"),
+            ("BIGINT UNSIGNED", "UInt64"),
+        ];
+        for (live, declared) in same {
+            assert_eq!(normalize_type(live), normalize_type(declared), "{live}");
+        }
+
+        let different = [
+            ("ARRAY(BIGINT)", "ARRAY(BIGINT NULL)"),
+            ("ARRAY(BIGINT)", "ARRAY(INT)"),
+            ("DECIMAL(38, 10)", "DECIMAL(38, 5)"),
+        ];
+        for (live, declared) in different {
+            assert_ne!(normalize_type(live), normalize_type(declared), "{live}");
+        }
+    }
+
+    #[test]
+    fn no_steps_when_live_columns_match() {
+        let live = |name: &str, data_type: &str, nullable| LiveColumn {
+            name: name.to_string(),
+            data_type: data_type.to_string(),
+            nullable,
+        };
+        let live_columns = [
+            live("site", "VARCHAR", false),
+            live("value", "Nullable(Float64)", true),
+            live("values", "Array(Nullable(Int64))", false),
+            live("ratio", "Nullable(Decimal(38, 10))", true),
+        ];
+        let (steps, warnings) = plan(
+            &TableDef::of::<Reading>(),
+            &Namespace::default(),
+            &live_columns,
+        );
+        assert!(steps.is_empty(), "{:?}", steps);
+        assert!(warnings.is_empty(), "{:?}", warnings);
+    }
+
+    #[tokio::test]
+    async fn dry_run_returns_destructive_steps() {
+        let conn = MockConnection::new();
+        conn.exec(
+            "CREATE TABLE reading (site VARCHAR NOT NULL, value DOUBLE NULL, \
+             values ARRAY(BIGINT NULL) NOT NULL, old INT NULL)",
+        )
+        .await
+        .unwrap();
+
+        let migration = migrate::<Reading>(&conn, MigrateOptions::default().dry_run(true))
+            .await
+            .unwrap();
+        assert!(!migration.applied);
+        let steps: Vec<_> = migration
+            .steps
+            .iter()
+            .map(|step| (step.sql.as_str(), step.destructive))
+            .collect();
+        assert_eq!(
+            steps,
+            [
+                (
+                    "ALTER TABLE `reading` ADD COLUMN `ratio` DECIMAL(38, 10) NULL;",
+                    false
+                ),
+                ("ALTER TABLE `reading` DROP COLUMN `old`;", true),
+            ]
+        );
+        assert!(!conn.executed().iter().any(|sql| sql.starts_with("ALTER")));
+
+        let refused = migrate::<Reading>(&conn, MigrateOptions::default()).await;
+        assert!(matches!(refused, Err(Error::Generation(_))));
+        assert!(!conn.executed().iter().any(|sql| sql.starts_with("ALTER")));
+    }
+
+    #[tokio::test]
+    async fn nullable_columns_are_not_tightened() {
+        let conn = MockConnection::new();
+        conn.exec(
+            "CREATE TABLE reading (site VARCHAR NULL, value DOUBLE NULL, \
+             values ARRAY(BIGINT NULL) NULL, ratio DECIMAL(38, 10) NULL)",
+        )
+        .await
+        .unwrap();
+
+        let migration = migrate::<Reading>(&conn, MigrateOptions::default())
+            .await
+            .unwrap();
+        assert!(migration.is_empty(), "{}", migration);
+        assert_eq!(migration.warnings.len(), 2);
+        assert!(migration.warnings[0].starts_with("Column site"));
+        assert!(migration.warnings[1].starts_with("Column values"));
+    }
+
+    #[tokio::test]
+    async fn making_columns_nullable_is_not_destructive() {
+        let conn = MockConnection::new();
+        conn.exec(
+            "CREATE TABLE reading (site VARCHAR NOT NULL, value DOUBLE NOT NULL, \
+             values ARRAY(BIGINT NULL) NOT NULL, ratio DECIMAL(38, 10) NULL)",
+        )
+        .await
+        .unwrap();
+
+        let migration = migrate::<Reading>(&conn, MigrateOptions::default())
+            .await
+            .unwrap();
+        assert!(migration.applied);
+        let steps: Vec<_> = migration
+            .steps
+            .iter()
+            .map(|step| (step.sql.as_str(), step.destructive))
+            .collect();
+        assert_eq!(
+            steps,
+            [(
+                "ALTER TABLE `reading` MODIFY COLUMN `value` DOUBLE NULL;",
+                false
+            )]
+        );
+        let migration = migrate::<Reading>(&conn, MigrateOptions::default())
+            .await
+            .unwrap();
+        assert!(migration.is_empty(), "{}", migration);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn requires_databend() {
+        let conn = crate::sqlite::SqliteConnection::open_in_memory().unwrap();
+        let error = migrate::<Reading>(&conn, MigrateOptions::default().dry_run(true))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&error, Error::Generation(message) if message == "migrate requires databend"),
+            "{}",
+            error
+        );
+    }
+}
@@ -0,0 +1,1346 @@
+//! An in-memory stand-in for a databend connection, for testing code built on this crate
+//! without a running server.
+//!
+//! [MockConnection] understands the statements this crate generates: `CREATE DATABASE`, `CREATE TABLE`,
+//! `INSERT` and `REPLACE` (from `VALUES` or a bulk loaded file), the `ALTER TABLE`
+//! statements of [crate::migrate], `DROP TABLE`, `TRUNCATE TABLE`, the `DELETE`s and
+//! simple `SELECT`s built by [crate::delete] and [crate::select], and the `BEGIN`, `COMMIT`
+//! and `ROLLBACK` of a [crate::transaction]. `WHERE` conditions are
+//! evaluated but `ORDER BY` is not, so a select returns matching rows in insert order, up to
+//! any `LIMIT`. Other statements are recorded and otherwise ignored.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Instant;
+
+use databend_driver::{NumberValue, Value};
+
+use crate::error::{Error, Result};
+use crate::executor::{Executor, WriteStats};
+use crate::load::LoadFormat;
+use crate::types::value_from_text;
+
+/// An [Executor] that keeps tables in memory.
+///
+/// Clones share the same state, so a clone can be handed to the code under test while the
+/// original is used to inspect what it did.
+#[derive(Clone, Default)]
+pub struct MockConnection {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    executed: Vec<String>,
+    databases: BTreeSet<String>,
+    /// Keyed by `database.table` for tables outside the current database.
+    tables: BTreeMap<String, MockTable>,
+    failures: Vec<Failure>,
+    /// Databases and tables as they were when the open transaction began, restored if it
+    /// is rolled back.
+    transaction: Option<(BTreeSet<String>, BTreeMap<String, MockTable>)>,
+}
+
+struct Failure {
+    pattern: String,
+    error: Error,
+    /// How many more matching statements fail. `None` fails every one.
+    remaining: Option<usize>,
+}
+
+#[derive(Default, Clone)]
+struct MockTable {
+    columns: Vec<MockColumn>,
+    rows: Vec<Vec<Value>>,
+}
+
+#[derive(Clone)]
+struct MockColumn {
+    name: String,
+    data_type: String,
+    nullable: bool,
+}
+
+impl MockConnection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every statement run so far, in order. Includes statements that failed.
+    pub fn executed(&self) -> Vec<String> {
+        self.state().executed.clone()
+    }
+
+    /// Names of the databases created, other than the current database.
+    pub fn databases(&self) -> Vec<String> {
+        self.state().databases.iter().cloned().collect()
+    }
+
+    /// Names of the tables that exist. Tables outside the current database are named
+    /// `database.table`.
+    pub fn tables(&self) -> Vec<String> {
+        self.state().tables.keys().cloned().collect()
+    }
+
+    /// The rows of `table`, in insert order. Empty if the table does not exist.
+    /// Name tables outside the current database `database.table`.
+    pub fn rows(&self, table: &str) -> Vec<Vec<Value>> {
+        self.state()
+            .tables
+            .get(table)
+            .map(|table| table.rows.clone())
+            .unwrap_or_default()
+    }
+
+    /// Whether a transaction has begun and not yet been committed or rolled back.
+    pub fn in_transaction(&self) -> bool {
+        self.state().transaction.is_some()
+    }
+
+    /// Make every statement containing `pattern` fail with `error`.
+    pub fn fail_on(&self, pattern: &str, error: Error) {
+        self.add_failure(pattern, error, None);
+    }
+
+    /// Make the next `times` statements containing `pattern` fail with `error`.
+    pub fn fail_times(&self, pattern: &str, error: Error, times: usize) {
+        if times > 0 {
+            self.add_failure(pattern, error, Some(times));
+        }
+    }
+
+    /// Remove all failures added with [MockConnection::fail_on] or [MockConnection::fail_times].
+    pub fn clear_failures(&self) {
+        self.state().failures.clear();
+    }
+
+    fn add_failure(&self, pattern: &str, error: Error, remaining: Option<usize>) {
+        self.state().failures.push(Failure {
+            pattern: pattern.to_string(),
+            error,
+            remaining,
+        });
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        // A panic in another test thread should not hide this connection's state
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[async_trait::async_trait]
+impl Executor for MockConnection {
+    async fn exec(&self, sql: &str) -> crate::Result<i64> {
+        let mut state = self.state();
+        state.record(sql)?;
+        state.exec(statement(sql))
+    }
+
+    async fn query(&self, sql: &str) -> crate::Result<Vec<Vec<Value>>> {
+        let mut state = self.state();
+        state.record(sql)?;
+        state.query(statement(sql))
+    }
+
+    async fn write(&self, sql: &str) -> crate::Result<WriteStats> {
+        let start = Instant::now();
+        let mut state = self.state();
+        state.record(sql)?;
+        let rows = state.exec(statement(sql))?;
+        Ok(write_stats(rows, start))
+    }
+
+    async fn load(
+        &self,
+        sql: &str,
+        format: LoadFormat,
+        data: Arc<[u8]>,
+    ) -> crate::Result<WriteStats> {
+        let start = Instant::now();
+        let mut state = self.state();
+        state.record(sql)?;
+        let data = std::str::from_utf8(&data)
+            .map_err(|e| rejected(format!("Loaded file is not valid UTF-8: {}", e)))?;
+        let rows = state.load(statement(sql), format, data)?;
+        Ok(write_stats(rows, start))
+    }
+}
+
+/// Stats for a statement that started at `start`. No bytes are written.
+fn write_stats(rows: i64, start: Instant) -> WriteStats {
+    WriteStats {
+        rows: rows as u64,
+        bytes: 0,
+        elapsed: start.elapsed(),
+    }
+}
+
+/// Statements the mock cannot run are reported the way a server would reject them,
+/// without an error code.
+fn rejected(message: String) -> Error {
+    Error::Server {
+        code: None,
+        message,
+    }
+}
+
+/// Return early with a [rejected] error.
+macro_rules! bail {
+    ($($arg:tt)*) => {
+        return Err(rejected(format!($($arg)*)))
+    };
+}
+
+/// The statement without surrounding whitespace or a trailing semicolon.
+fn statement(sql: &str) -> &str {
+    sql.trim().trim_end_matches(';').trim_end()
+}
+
+impl State {
+    /// Record a statement and fail it if it matches an injected failure.
+    fn record(&mut self, sql: &str) -> crate::Result<()> {
+        self.executed.push(sql.to_string());
+
+        let Some(index) = self
+            .failures
+            .iter()
+            .position(|failure| sql.contains(&failure.pattern))
+        else {
+            return Ok(());
+        };
+        let failure = &mut self.failures[index];
+        let error = failure.error.clone();
+        if let Some(remaining) = &mut failure.remaining {
+            *remaining -= 1;
+            if *remaining == 0 {
+                self.failures.remove(index);
+            }
+        }
+        Err(error)
+    }
+
+    fn table(&mut self, name: &str) -> Result<&mut MockTable> {
+        self.tables
+            .get_mut(name)
+            .ok_or_else(|| rejected(format!("Unknown table {}", name)))
+    }
+
+    fn exec(&mut self, sql: &str) -> Result<i64> {
+        if sql.eq_ignore_ascii_case("BEGIN") {
+            if self.transaction.is_some() {
+                bail!("A transaction is already open");
+            }
+            self.transaction = Some((self.databases.clone(), self.tables.clone()));
+        } else if sql.eq_ignore_ascii_case("COMMIT") {
+            if self.transaction.take().is_none() {
+                bail!("No transaction to commit");
+            }
+        } else if sql.eq_ignore_ascii_case("ROLLBACK") {
+            let Some((databases, tables)) = self.transaction.take() else {
+                bail!("No transaction to roll back");
+            };
+            self.databases = databases;
+            self.tables = tables;
+        } else if let Some(rest) = strip_keywords(sql, &["CREATE", "DATABASE"]) {
+            let rest = strip_keywords(rest, &["IF", "NOT", "EXISTS"]).unwrap_or(rest);
+            let (name, _) = identifier(rest)?;
+            self.databases.insert(name);
+        } else if let Some(rest) = strip_keywords(sql, &["CREATE", "TABLE"])
+            .or_else(|| strip_keywords(sql, &["CREATE", "TRANSIENT", "TABLE"]))
+        {
+            self.create_table(rest)?;
+        } else if let Some(rest) = strip_keywords(sql, &["DROP", "TABLE"]) {
+            let (if_exists, rest) = match strip_keywords(rest, &["IF", "EXISTS"]) {
+                Some(rest) => (true, rest),
+                None => (false, rest),
+            };
+            let (name, _) = table_name(rest)?;
+            if self.tables.remove(&name).is_none() && !if_exists {
+                bail!("Unknown table {}", name);
+            }
+        } else if let Some(rest) = strip_keywords(sql, &["TRUNCATE", "TABLE"]) {
+            let (name, _) = table_name(rest)?;
+            self.table(&name)?.rows.clear();
+        } else if let Some(rest) = strip_keywords(sql, &["DELETE", "FROM"]) {
+            let (name, clauses) = table_name(rest)?;
+            let table = self.table(&name)?;
+            let conditions = LiteralParser::new(clauses).conditions(table)?;
+            let before = table.rows.len();
+            table
+                .rows
+                .retain(|row| !conditions.iter().all(|condition| condition.matches(row)));
+            return Ok((before - table.rows.len()) as i64);
+        } else if let Some(rest) = strip_keywords(sql, &["ALTER", "TABLE"]) {
+            self.alter_table(rest)?;
+        } else if sql.starts_with("INSERT") || sql.starts_with("REPLACE") {
+            let (write, values) = Write::parse(sql)?;
+            let rows = LiteralParser::new(values).rows()?;
+            return self.write(write, rows);
+        }
+        Ok(0)
+    }
+
+    fn create_table(&mut self, sql: &str) -> Result<()> {
+        let (if_not_exists, sql) = match strip_keywords(sql, &["IF", "NOT", "EXISTS"]) {
+            Some(rest) => (true, rest),
+            None => (false, sql),
+        };
+        let (name, rest) = table_name(sql)?;
+        if let Some((database, _)) = name.split_once('.') {
+            if !self.databases.contains(database) {
+                bail!("Unknown database {}", database);
+            }
+        }
+        if self.tables.contains_key(&name) {
+            if if_not_exists {
+                return Ok(());
+            }
+            bail!("Table {} already exists", name);
+        }
+
+        // Anything after the column list, such as table options, is ignored
+        let (definitions, _) = parenthesized(rest)?;
+        let columns = split_top_level(definitions)
+            .into_iter()
+            .map(column_definition)
+            .collect::<Result<_>>()?;
+        self.tables.insert(
+            name,
+            MockTable {
+                columns,
+                rows: vec![],
+            },
+        );
+        Ok(())
+    }
+
+    fn alter_table(&mut self, sql: &str) -> Result<()> {
+        let (name, rest) = table_name(sql)?;
+        let table = self.table(&name)?;
+        if let Some(definition) = strip_keywords(rest, &["ADD", "COLUMN"]) {
+            table.columns.push(column_definition(definition)?);
+            for row in &mut table.rows {
+                row.push(Value::Null);
+            }
+        } else if let Some(definition) = strip_keywords(rest, &["MODIFY", "COLUMN"]) {
+            let column = column_definition(definition)?;
+            let index = table.column_index(&column.name)?;
+            table.columns[index] = column;
+        } else if let Some(column) = strip_keywords(rest, &["DROP", "COLUMN"]) {
+            let (column, _) = identifier(column)?;
+            let index = table.column_index(&column)?;
+            table.columns.remove(index);
+            for row in &mut table.rows {
+                row.remove(index);
+            }
+        } else {
+            bail!("Unsupported ALTER TABLE statement: {}", sql);
+        }
+        Ok(())
+    }
+
+    fn load(&mut self, sql: &str, format: LoadFormat, data: &str) -> Result<i64> {
+        let (mut write, values) = Write::parse(sql)?;
+        if !values.is_empty() {
+            bail!("A bulk load statement cannot include values: {}", sql);
+        }
+        let table = self.table(&write.table)?;
+
+        let rows = match format {
+            LoadFormat::Csv => {
+                let types: Vec<_> = match &write.columns {
+                    Some(columns) => columns
+                        .iter()
+                        .map(|column| {
+                            Ok(table.columns[table.column_index(column)?]
+                                .data_type
+                                .as_str())
+                        })
+                        .collect::<Result<_>>()?,
+                    None => table.columns.iter().map(|c| c.data_type.as_str()).collect(),
+                };
+                csv_rows(data, &types)?
+            }
+            LoadFormat::Ndjson => {
+                let mut rows = vec![];
+                for line in data.lines().filter(|line| !line.trim().is_empty()) {
+                    let serde_json::Value::Object(mut object) = serde_json::from_str(line)
+                        .map_err(|e| rejected(format!("Invalid NDJSON line: {}", e)))?
+                    else {
+                        bail!("NDJSON line is not an object: {}", line);
+                    };
+                    let row = table
+                        .columns
+                        .iter()
+                        .map(|column| match object.remove(&column.name) {
+                            None | Some(serde_json::Value::Null) => Literal::Null,
+                            // Variant columns keep the JSON itself
+                            Some(json) if base_type(&column.data_type) == "VARIANT" => {
+                                Literal::String(json.to_string())
+                            }
+                            Some(json) => json_literal(json),
+                        })
+                        .collect();
+                    rows.push(row);
+                }
+                // Values were read by column name, so they follow the table's column order
+                write.columns = None;
+                rows
+            }
+        };
+        self.write(write, rows)
+    }
+
+    /// Store rows from an `INSERT` or `REPLACE`, returning the number written.
+    fn write(&mut self, write: Write, rows: Vec<Vec<Literal>>) -> Result<i64> {
+        let table = self.table(&write.table)?;
+        let targets: Vec<usize> = match &write.columns {
+            Some(columns) => columns
+                .iter()
+                .map(|column| table.column_index(column))
+                .collect::<Result<_>>()?,
+            None => (0..table.columns.len()).collect(),
+        };
+        let keys: Vec<usize> = write
+            .keys
+            .iter()
+            .map(|column| table.column_index(column))
+            .collect::<Result<_>>()?;
+
+        // Convert every row before storing any, so a bad row leaves the table unchanged
+        let mut converted = vec![];
+        for row in rows {
+            if row.len() != targets.len() {
+                bail!(
+                    "Expected {} values for table {}, found {}",
+                    targets.len(),
+                    write.table,
+                    row.len()
+                );
+            }
+            let mut values = vec![Value::Null; table.columns.len()];
+            for (&index, literal) in targets.iter().zip(row) {
+                let column = &table.columns[index];
+                values[index] = to_value(literal, &column.data_type).map_err(|e| {
+                    rejected(format!("Invalid value for column {}: {}", column.name, e))
+                })?;
+            }
+            for (column, value) in table.columns.iter().zip(&values) {
+                if !column.nullable && *value == Value::Null {
+                    bail!("Column {} is not nullable", column.name);
+                }
+            }
+            converted.push(values);
+        }
+
+        let count = converted.len() as i64;
+        for values in converted {
+            // As in databend, a NULL key never matches, not even another NULL
+            if !keys.is_empty() {
+                table.rows.retain(|row| {
+                    !keys
+                        .iter()
+                        .all(|&key| row[key] != Value::Null && row[key] == values[key])
+                });
+            }
+            table.rows.push(values);
+        }
+        Ok(count)
+    }
+
+    fn query(&mut self, sql: &str) -> Result<Vec<Vec<Value>>> {
+        if sql.contains("FROM system.columns") {
+            return self.system_columns(sql);
+        }
+
+        let rest = strip_keywords(sql, &["SELECT"])
+            .ok_or_else(|| rejected(format!("Unsupported query: {}", sql)))?;
+        let from = rest
+            .find(" FROM ")
+            .ok_or_else(|| rejected(format!("Query has no FROM clause: {}", sql)))?;
+        let (name, clauses) = table_name(&rest[from + " FROM ".len()..])?;
+        let table = self.table(&name)?;
+
+        let projection: Vec<usize> = match rest[..from].trim() {
+            "*" => (0..table.columns.len()).collect(),
+            columns => split_top_level(columns)
+                .into_iter()
+                .map(|column| table.column_index(&identifier(column)?.0))
+                .collect::<Result<_>>()?,
+        };
+
+        let conditions = LiteralParser::new(clauses).conditions(table)?;
+        let limit = match clauses.rfind("LIMIT ") {
+            Some(index) => {
+                let limit = clauses[index + "LIMIT ".len()..].trim();
+                limit
+                    .parse()
+                    .map_err(|_| rejected(format!("Invalid LIMIT {}", limit)))?
+            }
+            None => usize::MAX,
+        };
+
+        Ok(table
+            .rows
+            .iter()
+            .filter(|row| conditions.iter().all(|condition| condition.matches(row)))
+            .take(limit)
+            .map(|row| projection.iter().map(|&index| row[index].clone()).collect())
+            .collect())
+    }
+
+    /// Answer the query [crate::migrate] uses to read a table's live columns.
+    fn system_columns(&self, sql: &str) -> Result<Vec<Vec<Value>>> {
+        // The string literal compared with `column`, if there is one
+        let string_after = |column: &str| {
+            let start = sql.find(column)? + column.len();
+            match LiteralParser::new(&sql[start..]).literal() {
+                Ok(Literal::String(value)) => Some(value),
+                _ => None,
+            }
+        };
+        let Some(mut name) = string_after("`table` = ") else {
+            bail!("Unsupported system.columns query: {}", sql);
+        };
+        // Anything other than a string, i.e. currentDatabase(), is the current database
+        if let Some(database) = string_after("`database` = ") {
+            name = format!("{}.{}", database, name);
+        }
+
+        let Some(table) = self.tables.get(&name) else {
+            return Ok(vec![]);
+        };
+        Ok(table
+            .columns
+            .iter()
+            .map(|column| {
+                vec![
+                    Value::String(column.name.clone()),
+                    Value::String(column.data_type.clone()),
+                    Value::String(if column.nullable { "YES" } else { "NO" }.to_string()),
+                ]
+            })
+            .collect())
+    }
+}
+
+impl MockTable {
+    fn column_index(&self, name: &str) -> Result<usize> {
+        self.columns
+            .iter()
+            .position(|column| column.name == name)
+            .ok_or_else(|| rejected(format!("Unknown column {}", name)))
+    }
+}
+
+/// The target of an `INSERT INTO t [(columns)] VALUES` or
+/// `REPLACE INTO t [(columns)] ON (keys) VALUES` statement.
+struct Write {
+    table: String,
+    columns: Option<Vec<String>>,
+    keys: Vec<String>,
+}
+
+impl Write {
+    /// Parse the statement, returning the target and the text after `VALUES`.
+    fn parse(sql: &str) -> Result<(Write, &str)> {
+        let (replace, rest) = if let Some(rest) = strip_keywords(sql, &["INSERT", "INTO"]) {
+            (false, rest)
+        } else if let Some(rest) = strip_keywords(sql, &["REPLACE", "INTO"]) {
+            (true, rest)
+        } else {
+            bail!("Unsupported statement: {}", sql);
+        };
+
+        let (table, mut rest) = table_name(rest)?;
+        let mut columns = None;
+        if rest.starts_with('(') {
+            let (list, after) = parenthesized(rest)?;
+            columns = Some(identifiers(list)?);
+            rest = after;
+        }
+        let mut keys = vec![];
+        if replace {
+            let on = strip_keywords(rest, &["ON"])
+                .ok_or_else(|| rejected(format!("REPLACE INTO needs ON (...): {}", sql)))?;
+            let (list, after) = parenthesized(on)?;
+            keys = identifiers(list)?;
+            rest = after;
+        }
+        let values = strip_keywords(rest, &["VALUES"])
+            .ok_or_else(|| rejected(format!("Expected VALUES: {}", sql)))?;
+
+        Ok((
+            Write {
+                table,
+                columns,
+                keys,
+            },
+            values,
+        ))
+    }
+}
+
+/// Strip leading keywords, matched case-insensitively and separated by whitespace.
+fn strip_keywords<'a>(sql: &'a str, keywords: &[&str]) -> Option<&'a str> {
+    let mut rest = sql.trim_start();
+    for keyword in keywords {
+        let head = rest.get(..keyword.len())?;
+        if !head.eq_ignore_ascii_case(keyword) {
+            return None;
+        }
+        let after = &rest[keyword.len()..];
+        if after.starts_with(|c: char| c.is_alphanumeric() || c == '_') {
+            return None;
+        }
+        rest = after.trim_start();
+    }
+    Some(rest)
+}
+
+/// Read an identifier, unquoting it if it is in backticks or double quotes.
+fn identifier(sql: &str) -> Result<(String, &str)> {
+    let sql = sql.trim_start();
+    if let Some(quote @ ('`' | '"')) = sql.chars().next() {
+        let mut name = String::new();
+        let mut chars = sql.char_indices().skip(1).peekable();
+        while let Some((i, c)) = chars.next() {
+            if c == quote {
+                // A doubled quote is an escaped quote
+                if chars.peek().is_some_and(|&(_, next)| next == quote) {
+                    chars.next();
+                    name.push(quote);
+                    continue;
+                }
+                return Ok((name, sql[i + 1..].trim_start()));
+            }
+            name.push(c);
+        }
+        bail!("Unterminated identifier: {}", sql);
+    }
+
+    let end = sql
+        .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+        .unwrap_or(sql.len());
+    if end == 0 {
+        bail!("Expected an identifier: {}", sql);
+    }
+    Ok((sql[..end].to_string(), sql[end..].trim_start()))
+}
+
+/// Read a table name, which may be qualified by its database as `database.table`.
+fn table_name(sql: &str) -> Result<(String, &str)> {
+    let (name, rest) = identifier(sql)?;
+    match rest.strip_prefix('.') {
+        Some(rest) => {
+            let (table, rest) = identifier(rest)?;
+            Ok((format!("{}.{}", name, table), rest))
+        }
+        None => Ok((name, rest)),
+    }
+}
+
+fn identifiers(list: &str) -> Result<Vec<String>> {
+    split_top_level(list)
+        .into_iter()
+        .map(|name| Ok(identifier(name)?.0))
+        .collect()
+}
+
+/// Parse a column definition as rendered by [crate::dialect::column_definition].
+fn column_definition(definition: &str) -> Result<MockColumn> {
+    let (name, rest) = identifier(definition)?;
+    // Drop any trailing clauses such as COMMENT or DEFAULT
+    let rest = [" COMMENT ", " DEFAULT "]
+        .iter()
+        .filter_map(|clause| rest.find(clause))
+        .min()
+        .map_or(rest, |end| &rest[..end])
+        .trim();
+    let (data_type, nullable) = if let Some(data_type) = rest.strip_suffix(" NOT NULL") {
+        (data_type, false)
+    } else if let Some(data_type) = rest.strip_suffix(" NULL") {
+        (data_type, true)
+    } else {
+        (rest, true)
+    };
+    Ok(MockColumn {
+        name,
+        data_type: data_type.trim().to_string(),
+        nullable,
+    })
+}
+
+/// Split the text inside a leading `(...)` from what follows it.
+fn parenthesized(sql: &str) -> Result<(&str, &str)> {
+    let sql = sql.trim_start();
+    if !sql.starts_with('(') {
+        bail!("Expected `(`: {}", sql);
+    }
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in sql.char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '\'' | '`' | '"' => quote = Some(c),
+                '(' => depth += 1,
+                ')' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok((&sql[1..i], sql[i + 1..].trim_start()));
+                    }
+                }
+                _ => {}
+            },
+        }
+    }
+    bail!("Unbalanced parentheses: {}", sql)
+}
+
+/// Split on commas that are not inside brackets or quotes.
+fn split_top_level(s: &str) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '\'' | '`' | '"' => quote = Some(c),
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth -= 1,
+                ',' if depth == 0 => {
+                    parts.push(s[start..i].trim());
+                    start = i + 1;
+                }
+                _ => {}
+            },
+        }
+    }
+    let last = s[start..].trim();
+    if !last.is_empty() {
+        parts.push(last);
+    }
+    parts
+}
+
+/// A condition of a `WHERE` clause, on the column at an index of the table.
+enum Condition {
+    Compare {
+        column: usize,
+        op: fn(Ordering) -> bool,
+        value: Value,
+    },
+    Between {
+        column: usize,
+        low: Value,
+        high: Value,
+    },
+    IsNull {
+        column: usize,
+        negated: bool,
+    },
+}
+
+impl Condition {
+    fn matches(&self, row: &[Value]) -> bool {
+        match self {
+            Condition::Compare { column, op, value } => {
+                compare(&row[*column], value).is_some_and(op)
+            }
+            Condition::Between { column, low, high } => {
+                compare(&row[*column], low).is_some_and(Ordering::is_ge)
+                    && compare(&row[*column], high).is_some_and(Ordering::is_le)
+            }
+            Condition::IsNull { column, negated } => {
+                matches!(row[*column], Value::Null) != *negated
+            }
+        }
+    }
+}
+
+/// Order two values of a column. `None` if either is NULL, as NULL never compares true.
+/// Other values only match `=`.
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Null, _) | (_, Value::Null) => None,
+        (Value::Number(a), Value::Number(b)) => number(a).partial_cmp(&number(b)),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Boolean(a), Value::Boolean(b)) => Some(a.cmp(b)),
+        (Value::Timestamp(a), Value::Timestamp(b)) => Some(a.cmp(b)),
+        (Value::Date(a), Value::Date(b)) => Some(a.cmp(b)),
+        (a, b) => (a == b).then_some(Ordering::Equal),
+    }
+}
+
+fn number(number: &NumberValue) -> f64 {
+    match number {
+        NumberValue::Decimal128(v, size) => *v as f64 / 10f64.powi(size.scale.into()),
+        // Other numbers display as plain decimal text
+        number => number.to_string().parse().unwrap_or(f64::NAN),
+    }
+}
+
+/// A SQL literal, before it is converted to the type of its column.
+#[derive(Debug)]
+enum Literal {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Literal>),
+    Tuple(Vec<Literal>),
+    Map(Vec<(Literal, Literal)>),
+}
+
+/// Parses the literals written by [crate::InsertValue].
+struct LiteralParser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl<'a> LiteralParser<'a> {
+    fn new(s: &'a str) -> Self {
+        LiteralParser {
+            chars: s.chars().peekable(),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => bail!("Expected `{}`, found `{}`", expected, c),
+            None => bail!("Expected `{}`", expected),
+        }
+    }
+
+    /// Consume `keyword` if it comes next, matched case-insensitively.
+    fn keyword(&mut self, keyword: &str) -> bool {
+        self.skip_whitespace();
+        let mut ahead = self.chars.clone();
+        for expected in keyword.chars() {
+            if ahead
+                .next_if(|c| c.eq_ignore_ascii_case(&expected))
+                .is_none()
+            {
+                return false;
+            }
+        }
+        if ahead
+            .peek()
+            .is_some_and(|c| c.is_alphanumeric() || *c == '_')
+        {
+            return false;
+        }
+        self.chars = ahead;
+        true
+    }
+
+    /// An identifier, unquoting it if it is in backticks or double quotes.
+    fn identifier(&mut self) -> Result<String> {
+        self.skip_whitespace();
+        let mut name = String::new();
+        if let Some(quote) = self.chars.next_if(|c| matches!(c, '`' | '"')) {
+            while let Some(c) = self.chars.next() {
+                if c == quote && self.chars.next_if_eq(&quote).is_none() {
+                    return Ok(name);
+                }
+                name.push(c);
+            }
+            bail!("Unterminated identifier");
+        }
+        while let Some(c) = self.chars.next_if(|c| c.is_alphanumeric() || *c == '_') {
+            name.push(c);
+        }
+        if name.is_empty() {
+            bail!("Expected an identifier");
+        }
+        Ok(name)
+    }
+
+    /// The conditions of an optional `WHERE` clause written by [crate::filter::Filter],
+    /// with values converted to the types of their columns in `table`.
+    fn conditions(mut self, table: &MockTable) -> Result<Vec<Condition>> {
+        let mut conditions = vec![];
+        if !self.keyword("WHERE") {
+            return Ok(conditions);
+        }
+        loop {
+            let column = table.column_index(&self.identifier()?)?;
+            let data_type = table.columns[column].data_type.as_str();
+            let condition = if self.keyword("BETWEEN") {
+                let low = to_value(self.literal()?, data_type)?;
+                if !self.keyword("AND") {
+                    bail!("Expected AND in BETWEEN");
+                }
+                let high = to_value(self.literal()?, data_type)?;
+                Condition::Between { column, low, high }
+            } else if self.keyword("IS") {
+                let negated = self.keyword("NOT");
+                if !self.keyword("NULL") {
+                    bail!("Expected NULL after IS");
+                }
+                Condition::IsNull { column, negated }
+            } else {
+                self.skip_whitespace();
+                let mut op = String::new();
+                while let Some(c) = self.chars.next_if(|c| matches!(c, '<' | '>' | '=' | '!')) {
+                    op.push(c);
+                }
+                let op = match op.as_str() {
+                    "=" => Ordering::is_eq,
+                    "<>" | "!=" => Ordering::is_ne,
+                    "<" => Ordering::is_lt,
+                    "<=" => Ordering::is_le,
+                    ">" => Ordering::is_gt,
+                    ">=" => Ordering::is_ge,
+                    op => bail!("Unsupported operator `{}`", op),
+                };
+                let value = to_value(self.literal()?, data_type)?;
+                Condition::Compare { column, op, value }
+            };
+            conditions.push(condition);
+            if !self.keyword("AND") {
+                return Ok(conditions);
+            }
+        }
+    }
+
+    /// The rows of a `VALUES` clause: `(a, b), (c, d)`.
+    fn rows(mut self) -> Result<Vec<Vec<Literal>>> {
+        let mut rows = vec![];
+        loop {
+            self.skip_whitespace();
+            if self.chars.peek().is_none() {
+                return Ok(rows);
+            }
+            if !rows.is_empty() {
+                self.expect(',')?;
+            }
+            self.expect('(')?;
+            rows.push(self.list(')')?);
+        }
+    }
+
+    /// Comma separated literals up to `close`, after the opening bracket.
+    fn list(&mut self, close: char) -> Result<Vec<Literal>> {
+        let mut items = vec![];
+        self.skip_whitespace();
+        if self.chars.next_if_eq(&close).is_some() {
+            return Ok(items);
+        }
+        loop {
+            items.push(self.literal()?);
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => {
+                    // A trailing comma, as in the one element tuple `(1,)`
+                    self.skip_whitespace();
+                    if self.chars.next_if_eq(&close).is_some() {
+                        return Ok(items);
+                    }
+                }
+                Some(c) if c == close => return Ok(items),
+                Some(c) => bail!("Expected `,` or `{}`, found `{}`", close, c),
+                None => bail!("Expected `{}`", close),
+            }
+        }
+    }
+
+    fn literal(&mut self) -> Result<Literal> {
+        self.skip_whitespace();
+        let Some(&c) = self.chars.peek() else {
+            bail!("Expected a value");
+        };
+        match c {
+            '\'' => {
+                self.chars.next();
+                self.string().map(Literal::String)
+            }
+            '[' => {
+                self.chars.next();
+                self.list(']').map(Literal::Array)
+            }
+            '(' => {
+                self.chars.next();
+                self.list(')').map(Literal::Tuple)
+            }
+            '{' => {
+                self.chars.next();
+                self.map().map(Literal::Map)
+            }
+            c if c == '-' || c == '+' || c == '.' || c.is_ascii_digit() => {
+                let mut number = String::new();
+                while let Some(c) = self
+                    .chars
+                    .next_if(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '+' | '.'))
+                {
+                    number.push(c);
+                }
+                Ok(Literal::Number(number))
+            }
+            c if c.is_ascii_alphabetic() => {
+                let mut word = String::new();
+                while let Some(c) = self.chars.next_if(|c| c.is_ascii_alphanumeric()) {
+                    word.push(c);
+                }
+                match word.to_ascii_uppercase().as_str() {
+                    "NULL" => Ok(Literal::Null),
+                    "TRUE" => Ok(Literal::Bool(true)),
+                    "FALSE" => Ok(Literal::Bool(false)),
+                    _ => bail!("Unexpected `{}`", word),
+                }
+            }
+            c => bail!("Unexpected `{}`", c),
+        }
+    }
+
+    /// A quoted string, after the opening quote.
+    fn string(&mut self) -> Result<String> {
+        let mut s = String::new();
+        while let Some(c) = self.chars.next() {
+            match c {
+                '\\' => match self.chars.next() {
+                    Some('n') => s.push('\n'),
+                    Some('r') => s.push('\r'),
+                    Some('t') => s.push('\t'),
+                    Some('0') => s.push('\0'),
+                    Some(c) => s.push(c),
+                    None => break,
+                },
+                // '' is also an escaped quote
+                '\'' if self.chars.next_if_eq(&'\'').is_some() => s.push('\''),
+                '\'' => return Ok(s),
+                c => s.push(c),
+            }
+        }
+        bail!("Unterminated string")
+    }
+
+    /// `key: value` pairs, after the opening brace.
+    fn map(&mut self) -> Result<Vec<(Literal, Literal)>> {
+        let mut entries = vec![];
+        self.skip_whitespace();
+        if self.chars.next_if_eq(&'}').is_some() {
+            return Ok(entries);
+        }
+        loop {
+            let key = self.literal()?;
+            self.expect(':')?;
+            entries.push((key, self.literal()?));
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => {}
+                Some('}') => return Ok(entries),
+                Some(c) => bail!("Expected `,` or `}}`, found `{}`", c),
+                None => bail!("Expected `}}`"),
+            }
+        }
+    }
+}
+
+fn json_literal(json: serde_json::Value) -> Literal {
+    match json {
+        serde_json::Value::Null => Literal::Null,
+        serde_json::Value::Bool(v) => Literal::Bool(v),
+        serde_json::Value::Number(v) => Literal::Number(v.to_string()),
+        serde_json::Value::String(v) => Literal::String(v),
+        serde_json::Value::Array(values) => {
+            Literal::Array(values.into_iter().map(json_literal).collect())
+        }
+        serde_json::Value::Object(entries) => Literal::Map(
+            entries
+                .into_iter()
+                .map(|(k, v)| (Literal::String(k), json_literal(v)))
+                .collect(),
+        ),
+    }
+}
+
+/// Parse CSV as written by [crate::BulkLoad], with one entry in `types` per field.
+fn csv_rows(data: &str, types: &[&str]) -> Result<Vec<Vec<Literal>>> {
+    let mut rows = vec![];
+    let mut row = vec![];
+    let mut chars = data.chars().peekable();
+    while chars.peek().is_some() {
+        let data_type = types
+            .get(row.len())
+            .ok_or_else(|| rejected(format!("CSV row has more than {} fields", types.len())))?;
+
+        let field = if chars.next_if_eq(&'"').is_some() {
+            let mut text = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') if chars.next_if_eq(&'"').is_some() => text.push('"'),
+                    Some('"') => break,
+                    Some(c) => text.push(c),
+                    None => bail!("Unterminated CSV field"),
+                }
+            }
+            if is_nested(data_type) {
+                LiteralParser::new(&text).literal()?
+            } else {
+                Literal::String(text)
+            }
+        } else {
+            let mut text = String::new();
+            while let Some(c) = chars.next_if(|&c| c != ',' && c != '\n') {
+                text.push(c);
+            }
+            match text.as_str() {
+                "\\N" => Literal::Null,
+                _ => Literal::String(text),
+            }
+        };
+        row.push(field);
+
+        match chars.next() {
+            Some(',') => {}
+            Some('\n') | None => rows.push(std::mem::take(&mut row)),
+            Some(c) => bail!("Unexpected `{}` after CSV field", c),
+        }
+    }
+    Ok(rows)
+}
+
+/// The type inside `NAME(...)`, if `data_type` is of that form.
+fn type_arguments<'a>(data_type: &'a str, name: &str) -> Option<&'a str> {
+    data_type
+        .strip_prefix(name)?
+        .strip_prefix('(')?
+        .strip_suffix(')')
+}
+
+/// The type without a `NULLABLE(...)` wrapper.
+fn base_type(data_type: &str) -> &str {
+    type_arguments(data_type, "NULLABLE").unwrap_or(data_type)
+}
+
+fn is_nested(data_type: &str) -> bool {
+    let data_type = base_type(data_type);
+    ["ARRAY(", "MAP(", "TUPLE("]
+        .iter()
+        .any(|prefix| data_type.starts_with(prefix))
+}
+
+/// Convert a literal into the value databend would store for a column of `data_type`.
+fn to_value(literal: Literal, data_type: &str) -> Result<Value> {
+    let data_type = base_type(data_type);
+    let mismatch =
+        |literal: &Literal| rejected(format!("Cannot store {:?} as {}", literal, data_type));
+
+    if let Literal::Null = literal {
+        return Ok(Value::Null);
+    }
+    if let Some(element) = type_arguments(data_type, "ARRAY") {
+        let Literal::Array(items) = literal else {
+            return Err(mismatch(&literal));
+        };
+        return Ok(Value::Array(
+            items
+                .into_iter()
+                .map(|item| to_value(item, element))
+                .collect::<Result<_>>()?,
+        ));
+    }
+    if let Some(arguments) = type_arguments(data_type, "MAP") {
+        let [key_type, value_type] = split_top_level(arguments)[..] else {
+            bail!("Invalid MAP type {}", data_type);
+        };
+        let Literal::Map(entries) = literal else {
+            return Err(mismatch(&literal));
+        };
+        return Ok(Value::Map(
+            entries
+                .into_iter()
+                .map(|(k, v)| Ok((to_value(k, key_type)?, to_value(v, value_type)?)))
+                .collect::<Result<_>>()?,
+        ));
+    }
+    if let Some(arguments) = type_arguments(data_type, "TUPLE") {
+        let types = split_top_level(arguments);
+        // Loaded JSON has no tuples, so they arrive as arrays
+        let (Literal::Tuple(items) | Literal::Array(items)) = literal else {
+            return Err(mismatch(&literal));
+        };
+        if items.len() != types.len() {
+            bail!(
+                "Expected {} tuple elements, found {}",
+                types.len(),
+                items.len()
+            );
+        }
+        return Ok(Value::Tuple(
+            items
+                .into_iter()
+                .zip(types)
+                .map(|(item, element)| to_value(item, element))
+                .collect::<Result<_>>()?,
+        ));
+    }
+
+    let text = match literal {
+        Literal::Bool(v) => v.to_string(),
+        Literal::Number(text) | Literal::String(text) => text,
+        literal => return Err(mismatch(&literal)),
+    };
+    // A value the server cannot convert is a rejected statement, not a conversion error
+    value_from_text(text, data_type).map_err(|e| rejected(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{create, delete, insert, select, upsert, AirbendTable};
+
+    #[derive(AirbendTable, Debug, PartialEq)]
+    #[airbend_table(table_name = "reading")]
+    struct Reading {
+        #[airbend_col(key)]
+        site_code: String,
+        species_code: Option<String>,
+        value: Option<f64>,
+        tags: Vec<String>,
+    }
+
+    fn reading(site_code: &str, species_code: Option<&str>, value: f64) -> Reading {
+        Reading {
+            site_code: site_code.to_string(),
+            species_code: species_code.map(str::to_string),
+            value: Some(value),
+            tags: vec!["it's".to_string(), "a\\b".to_string()],
+        }
+    }
+
+    #[tokio::test]
+    async fn rows_round_trip() {
+        let conn = MockConnection::new();
+        create::<Reading>(&conn).await.unwrap();
+        let rows = [reading("MY1", Some("NO2"), 1.5), reading("MY2", None, 2.5)];
+        let stats = insert().values(&rows).execute(&conn).await.unwrap();
+        assert_eq!(stats.rows, 2);
+
+        let fetched = select::<Reading>().fetch(&conn).await.unwrap();
+        assert_eq!(fetched, rows);
+
+        let fetched = select::<Reading>()
+            .where_gt("value", 2.0)
+            .fetch(&conn)
+            .await
+            .unwrap();
+        assert_eq!(fetched, [reading("MY2", None, 2.5)]);
+    }
+
+    #[tokio::test]
+    async fn replace_matches_keys() {
+        let conn = MockConnection::new();
+        create::<Reading>(&conn).await.unwrap();
+        let rows = [reading("MY1", Some("NO2"), 1.0), reading("MY2", None, 1.0)];
+        upsert()
+            .values(&rows)
+            .unwrap()
+            .execute(&conn)
+            .await
+            .unwrap();
+        let rows = [reading("MY1", None, 2.0)];
+        upsert()
+            .values(&rows)
+            .unwrap()
+            .execute(&conn)
+            .await
+            .unwrap();
+
+        let fetched = select::<Reading>().fetch(&conn).await.unwrap();
+        assert_eq!(
+            fetched,
+            [reading("MY2", None, 1.0), reading("MY1", None, 2.0)]
+        );
+    }
+
+    #[tokio::test]
+    async fn bulk_loads_match_inserted_values() {
+        let rows = [
+            reading("MY1", Some("NO2"), 1.5),
+            reading("MY2", None, -0.25),
+        ];
+        for format in [LoadFormat::Csv, LoadFormat::Ndjson] {
+            let conn = MockConnection::new();
+            create::<Reading>(&conn).await.unwrap();
+            let load = insert().load(&rows, format).unwrap();
+            let stats = load.execute(&conn).await.unwrap();
+            assert_eq!(stats.rows, 2);
+            assert_eq!(select::<Reading>().fetch(&conn).await.unwrap(), rows);
+        }
+    }
+
+    #[tokio::test]
+    async fn deletes_matching_rows() {
+        let conn = MockConnection::new();
+        create::<Reading>(&conn).await.unwrap();
+        let rows = [
+            reading("MY1", Some("NO2"), 1.0),
+            reading("MY1", None, 2.0),
+            reading("MY2", None, 3.0),
+        ];
+        insert().values(&rows).execute(&conn).await.unwrap();
+
+        let deleted = delete::<Reading>()
+            .where_eq("site_code", "MY1")
+            .where_null("species_code")
+            .execute(&conn)
+            .await
+            .unwrap();
+        assert_eq!(deleted, 1);
+        assert_eq!(conn.rows("reading").len(), 2);
+    }
+
+    #[tokio::test]
+    async fn rejects_what_a_server_would() {
+        let conn = MockConnection::new();
+        let unknown = conn.exec("INSERT INTO missing VALUES (1)").await;
+        assert!(matches!(
+            unknown,
+            Err(Error::Server { code: None, ref message }) if message == "Unknown table missing"
+        ));
+
+        conn.exec("CREATE TABLE t (a INT NOT NULL)").await.unwrap();
+        let null = conn.exec("INSERT INTO t VALUES (NULL)").await;
+        assert!(matches!(null, Err(Error::Server { code: None, .. })));
+        let mismatch = conn.exec("INSERT INTO t VALUES ('x')").await;
+        assert!(matches!(mismatch, Err(Error::Server { code: None, .. })));
+        assert!(conn.rows("t").is_empty());
+    }
+
+    #[tokio::test]
+    async fn injected_failures() {
+        let conn = MockConnection::new();
+        let error = Error::Connection("reset".to_string());
+        conn.fail_times("CREATE", error.clone(), 1);
+        assert!(conn.exec("CREATE TABLE t (a INT)").await.is_err());
+        conn.exec("CREATE TABLE t (a INT)").await.unwrap();
+
+        conn.fail_on("INSERT", error);
+        assert!(conn.exec("INSERT INTO t VALUES (1)").await.is_err());
+        assert!(conn.exec("INSERT INTO t VALUES (1)").await.is_err());
+        conn.clear_failures();
+        conn.exec("INSERT INTO t VALUES (1)").await.unwrap();
+
+        assert_eq!(conn.executed().len(), 5);
+        assert_eq!(conn.rows("t"), [[Value::Number(NumberValue::Int32(1))]]);
+    }
+}
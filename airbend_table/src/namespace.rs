@@ -0,0 +1,226 @@
+use std::ops::Deref;
+use std::sync::Arc;
+
+use databend_driver::Value;
+
+use crate::dialect::Dialect;
+use crate::error::Result;
+use crate::executor::{Executor, WriteStats};
+use crate::load::LoadFormat;
+use crate::registry::TableDef;
+
+/// Where tables live at runtime, so several environments (dev, staging, one per developer)
+/// can share a databend instance.
+///
+/// A namespace can move every table into another database and add a prefix or suffix to
+/// table names. Statements run through a [Namespaced] connection use it for creates, inserts,
+/// queries and migrations alike.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Namespace {
+    database: Option<String>,
+    table_prefix: String,
+    table_suffix: String,
+}
+
+/// Used by connections without a namespace: tables keep their own names and databases.
+pub(crate) static DEFAULT_NAMESPACE: Namespace = Namespace::new();
+
+impl Namespace {
+    pub const fn new() -> Self {
+        Namespace {
+            database: None,
+            table_prefix: String::new(),
+            table_suffix: String::new(),
+        }
+    }
+
+    /// Put every table in `database`, including tables declared with
+    /// `#[airbend_table(database = "...")]`.
+    pub fn database(mut self, database: impl Into<String>) -> Self {
+        self.database = Some(database.into());
+        self
+    }
+
+    /// Prepend `prefix` to every table name.
+    pub fn table_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.table_prefix = prefix.into();
+        self
+    }
+
+    /// Append `suffix` to every table name.
+    pub fn table_suffix(mut self, suffix: impl Into<String>) -> Self {
+        self.table_suffix = suffix.into();
+        self
+    }
+
+    /// The name `table` is stored under.
+    pub fn table_name(&self, table: &TableDef) -> String {
+        format!("{}{}{}", self.table_prefix, table.name(), self.table_suffix)
+    }
+
+    /// The database `table` is stored in. `None` is the connection's current database.
+    pub fn database_of(&self, table: &TableDef) -> Option<&str> {
+        self.database.as_deref().or(table.database())
+    }
+
+    /// `table` as it is referred to in statements in `dialect`.
+    pub(crate) fn table(&self, dialect: &dyn Dialect, table: &TableDef) -> String {
+        dialect.qualified_table(self.database_of(table), &self.table_name(table))
+    }
+
+    /// Run every statement sent through `conn` in this namespace.
+    pub fn connect<C>(self, conn: C) -> Namespaced<C> {
+        Namespaced {
+            conn,
+            namespace: self,
+        }
+    }
+}
+
+/// A connection whose statements are run in a [Namespace]. Created with [Namespace::connect].
+///
+/// Wraps anything that dereferences to an [Executor], such as the `Box<dyn Connection>`
+/// returned by databend's client or a reference to a [crate::mock::MockConnection].
+#[derive(Clone)]
+pub struct Namespaced<C> {
+    conn: C,
+    namespace: Namespace,
+}
+
+impl<C> Namespaced<C> {
+    /// The wrapped connection.
+    pub fn inner(&self) -> &C {
+        &self.conn
+    }
+}
+
+#[async_trait::async_trait]
+impl<C> Executor for Namespaced<C>
+where
+    C: Deref + Send + Sync,
+    C::Target: Executor,
+{
+    async fn exec(&self, sql: &str) -> Result<i64> {
+        self.conn.exec(sql).await
+    }
+
+    async fn query(&self, sql: &str) -> Result<Vec<Vec<Value>>> {
+        self.conn.query(sql).await
+    }
+
+    async fn write(&self, sql: &str) -> Result<WriteStats> {
+        self.conn.write(sql).await
+    }
+
+    async fn load(&self, sql: &str, format: LoadFormat, data: Arc<[u8]>) -> Result<WriteStats> {
+        self.conn.load(sql, format, data).await
+    }
+
+    fn dialect(&self) -> &dyn Dialect {
+        self.conn.dialect()
+    }
+
+    fn namespace(&self) -> &Namespace {
+        &self.namespace
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockConnection;
+    use crate::{
+        create, delete, insert, migrate, select, upsert, AirbendTable, Databend, MigrateOptions,
+        SinkOptions,
+    };
+
+    #[derive(AirbendTable, Debug, PartialEq)]
+    #[airbend_table(table_name = "reading", database = "raw")]
+    struct Reading {
+        #[airbend_col(key)]
+        site_code: String,
+        value: f64,
+    }
+
+    fn reading(site_code: &str, value: f64) -> Reading {
+        Reading {
+            site_code: site_code.to_string(),
+            value,
+        }
+    }
+
+    #[test]
+    fn names_tables() {
+        let table = TableDef::of::<Reading>();
+        assert_eq!(Namespace::new().table(&Databend, &table), "`raw`.`reading`");
+
+        let namespace = Namespace::new()
+            .database("dev")
+            .table_prefix("alice_")
+            .table_suffix("_v2");
+        assert_eq!(namespace.table_name(&table), "alice_reading_v2");
+        assert_eq!(namespace.database_of(&table), Some("dev"));
+        assert_eq!(
+            namespace.table(&Databend, &table),
+            "`dev`.`alice_reading_v2`"
+        );
+    }
+
+    #[tokio::test]
+    async fn builders_use_the_namespace() {
+        let mock = MockConnection::new();
+        let conn = Namespace::new()
+            .database("dev")
+            .table_prefix("alice_")
+            .connect(&mock);
+
+        create::<Reading>(&conn).await.unwrap();
+        assert_eq!(mock.executed()[0], "CREATE DATABASE IF NOT EXISTS `dev`;");
+        assert_eq!(mock.databases(), ["dev"]);
+        assert_eq!(mock.tables(), ["dev.alice_reading"]);
+
+        insert()
+            .values([reading("MY1", 1.0)])
+            .execute(&conn)
+            .await
+            .unwrap();
+        upsert()
+            .values([reading("MY1", 2.0)])
+            .unwrap()
+            .execute(&conn)
+            .await
+            .unwrap();
+        let sink = insert().sink(SinkOptions::default());
+        let outcome = sink
+            .run(&conn, futures::stream::iter([reading("MY2", 3.0)]))
+            .await;
+        outcome.result.unwrap();
+        assert_eq!(
+            select::<Reading>().fetch(&conn).await.unwrap(),
+            [reading("MY1", 2.0), reading("MY2", 3.0)]
+        );
+
+        let deleted = delete::<Reading>()
+            .where_eq("site_code", "MY2")
+            .execute(&conn)
+            .await
+            .unwrap();
+        assert_eq!(deleted, 1);
+        assert_eq!(mock.rows("dev.alice_reading").len(), 1);
+
+        // Looking at any other table would plan to create it
+        let migration = migrate::<Reading>(&conn, MigrateOptions::default())
+            .await
+            .unwrap();
+        assert!(migration.steps.is_empty(), "{:?}", migration.steps);
+
+        for sql in &mock.executed()[1..] {
+            assert!(
+                sql.contains("`dev`.`alice_reading`")
+                    || sql.contains("`database` = 'dev' AND `table` = 'alice_reading'"),
+                "{}",
+                sql
+            );
+        }
+    }
+}
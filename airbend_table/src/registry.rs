@@ -0,0 +1,143 @@
+//! Every type that derives [crate::AirbendTable] registers itself here, so all tables linked
+//! into a binary can be created, migrated or listed without naming each one.
+//!
+//! Registration uses the `inventory` crate: the derive submits a [TableDef] for the type,
+//! and the submissions of every crate linked into the binary are collected when it starts,
+//! so there is no list to keep up to date by hand. Tables with type or const parameters
+//! are not registered, since the derive cannot know which parameters will be used.
+//! Lifetime parameters don't stop a table being registered.
+
+use std::collections::BTreeSet;
+
+use crate::dialect::Dialect;
+use crate::error::{Error, Result};
+use crate::executor::Executor;
+use crate::migrate::{migrate_table, MigrateOptions, Migration};
+use crate::namespace::Namespace;
+use crate::tables::{Field, Table, TableOptions};
+
+/// A type-erased [Table]: its name, database, schema and options.
+#[derive(Clone, Copy)]
+pub struct TableDef {
+    name: fn() -> &'static str,
+    database: fn() -> Option<&'static str>,
+    schema: fn() -> Vec<Field>,
+    options: fn() -> TableOptions,
+}
+
+inventory::collect!(TableDef);
+
+impl TableDef {
+    pub const fn of<T: Table>() -> Self {
+        TableDef {
+            name: T::name,
+            database: T::database,
+            schema: T::schema,
+            options: T::options,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        (self.name)()
+    }
+
+    pub fn database(&self) -> Option<&'static str> {
+        (self.database)()
+    }
+
+    pub fn schema(&self) -> Vec<Field> {
+        (self.schema)()
+    }
+
+    pub fn options(&self) -> TableOptions {
+        (self.options)()
+    }
+
+    /// The `CREATE TABLE` statement for this table in `dialect`, placed in `namespace`.
+    pub fn create_sql(&self, dialect: &dyn Dialect, namespace: &Namespace) -> String {
+        dialect.create_table(
+            &namespace.table(dialect, self),
+            &self.schema(),
+            &self.options(),
+        )
+    }
+}
+
+/// Every registered table, ordered by name.
+pub fn tables() -> Vec<&'static TableDef> {
+    let mut tables: Vec<_> = inventory::iter::<TableDef>.into_iter().collect();
+    tables.sort_by_key(|table| table.name());
+    tables
+}
+
+/// Registered tables, failing if two types use the same table name.
+fn unique_tables() -> Result<Vec<&'static TableDef>> {
+    let tables = tables();
+    for pair in tables.windows(2) {
+        if pair[0].name() == pair[1].name() {
+            return Err(Error::Generation(format!(
+                "More than one type is registered for table {}",
+                pair[0].name()
+            )));
+        }
+    }
+    Ok(tables)
+}
+
+/// Create every registered table that does not already exist, and any databases they
+/// are placed in.
+pub async fn create_all(conn: &(impl Executor + ?Sized)) -> Result<()> {
+    let tables = unique_tables()?;
+    for sql in create_databases_sql(conn.dialect(), conn.namespace(), &tables) {
+        conn.exec(&sql).await?;
+    }
+    for table in tables {
+        conn.exec(&table.create_sql(conn.dialect(), conn.namespace()))
+            .await?;
+    }
+    Ok(())
+}
+
+/// `CREATE DATABASE` statements for every database `tables` are placed in.
+fn create_databases_sql(
+    dialect: &dyn Dialect,
+    namespace: &Namespace,
+    tables: &[&TableDef],
+) -> Vec<String> {
+    let databases: BTreeSet<_> = tables
+        .iter()
+        .filter_map(|table| namespace.database_of(table))
+        .collect();
+    databases
+        .into_iter()
+        .filter_map(|database| dialect.create_database(database))
+        .collect()
+}
+
+/// Run [crate::migrate] for every registered table, in name order. Stops at the first
+/// table that fails, leaving the later tables untouched.
+pub async fn migrate_all(
+    conn: &(impl Executor + ?Sized),
+    options: MigrateOptions,
+) -> Result<Vec<Migration>> {
+    let mut migrations = vec![];
+    for table in unique_tables()? {
+        migrations.push(migrate_table(conn, table, options.clone()).await?);
+    }
+    Ok(migrations)
+}
+
+/// The `CREATE DATABASE` and `CREATE TABLE` statements for every registered table, one per
+/// line, for review.
+pub fn ddl(dialect: &dyn Dialect, namespace: &Namespace) -> String {
+    let tables = tables();
+    create_databases_sql(dialect, namespace, &tables)
+        .into_iter()
+        .chain(
+            tables
+                .iter()
+                .map(|table| table.create_sql(dialect, namespace)),
+        )
+        .map(|sql| sql + "\n")
+        .collect()
+}
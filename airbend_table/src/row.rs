@@ -0,0 +1,224 @@
+use databend_driver::Value;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::dialect::{Dialect, Literal, StringLiteral};
+use crate::error::Result;
+use crate::tables::{InsertValue, Table};
+
+/// Writes the values of a row as SQL literals, straight into the statement being built.
+/// Passed to [Table::write_row], so rows are written without first being converted to
+/// [InsertValue]s.
+pub struct RowWriter<'a> {
+    dialect: &'a dyn Dialect,
+    sql: &'a mut String,
+    /// Values written so far, to place the separators between them.
+    values: usize,
+}
+
+impl RowWriter<'_> {
+    /// Write the next value of the row. Fails if the value cannot be converted, such as a
+    /// variant that does not serialize as JSON.
+    pub fn value<V: ToValue + ?Sized>(&mut self, value: &V) -> Result<()> {
+        if self.values > 0 {
+            self.sql.push_str(", ");
+        }
+        self.values += 1;
+        value.write_value(self)
+    }
+
+    /// Write `value` as a literal. For [ToValue::write_value] implementations: unlike
+    /// [RowWriter::value], this does not start a new value. Fails with
+    /// [crate::Error::Conversion] if the dialect has no literal for the value.
+    pub fn literal(&mut self, value: &InsertValue) -> Result<()> {
+        Literal {
+            dialect: self.dialect,
+            value: &value.0,
+        }
+        .write_to(self.sql)
+    }
+
+    /// Write `s` as a string literal, without copying it into an [InsertValue] first.
+    /// For [ToValue::write_value] implementations, like [RowWriter::literal].
+    pub fn string(&mut self, s: &str) -> Result<()> {
+        StringLiteral {
+            dialect: self.dialect,
+            value: s,
+        }
+        .write_to(self.sql)
+    }
+}
+
+/// Append `row` to `sql` as a parenthesised list of literals.
+pub(crate) fn write_row<T: Table + ?Sized>(
+    sql: &mut String,
+    dialect: &dyn Dialect,
+    row: &T,
+) -> Result<()> {
+    sql.push('(');
+    row.write_row(&mut RowWriter {
+        dialect,
+        sql,
+        values: 0,
+    })?;
+    sql.push(')');
+    Ok(())
+}
+
+/// Convert a borrowed Rust value into a value that can be inserted. Implemented for every
+/// type with a [From] conversion into [InsertValue], so table rows can be written without
+/// giving them up. Conversions fail with [crate::Error::Conversion], for values such as
+/// variants that cannot be encoded.
+pub trait ToValue {
+    fn to_value(&self) -> Result<InsertValue>;
+
+    /// Write the value as a literal. Types holding strings override this to write them
+    /// without copying.
+    fn write_value(&self, writer: &mut RowWriter<'_>) -> Result<()> {
+        writer.literal(&self.to_value()?)
+    }
+}
+
+impl<T: ToValue + ?Sized> ToValue for &T {
+    fn to_value(&self) -> Result<InsertValue> {
+        (**self).to_value()
+    }
+
+    fn write_value(&self, writer: &mut RowWriter<'_>) -> Result<()> {
+        (**self).write_value(writer)
+    }
+}
+
+impl ToValue for InsertValue {
+    fn to_value(&self) -> Result<InsertValue> {
+        Ok(InsertValue(self.0.clone()))
+    }
+
+    fn write_value(&self, writer: &mut RowWriter<'_>) -> Result<()> {
+        writer.literal(self)
+    }
+}
+
+impl<T: ToValue> ToValue for Option<T> {
+    fn to_value(&self) -> Result<InsertValue> {
+        match self {
+            Some(v) => v.to_value(),
+            None => Ok(InsertValue(Value::Null)),
+        }
+    }
+
+    fn write_value(&self, writer: &mut RowWriter<'_>) -> Result<()> {
+        match self {
+            Some(v) => v.write_value(writer),
+            None => writer.literal(&InsertValue(Value::Null)),
+        }
+    }
+}
+
+impl ToValue for str {
+    fn to_value(&self) -> Result<InsertValue> {
+        Ok(self.into())
+    }
+
+    fn write_value(&self, writer: &mut RowWriter<'_>) -> Result<()> {
+        writer.string(self)
+    }
+}
+
+impl ToValue for String {
+    fn to_value(&self) -> Result<InsertValue> {
+        Ok(self.into())
+    }
+
+    fn write_value(&self, writer: &mut RowWriter<'_>) -> Result<()> {
+        writer.string(self)
+    }
+}
+
+impl ToValue for Cow<'_, str> {
+    fn to_value(&self) -> Result<InsertValue> {
+        Ok(self.as_ref().into())
+    }
+
+    fn write_value(&self, writer: &mut RowWriter<'_>) -> Result<()> {
+        writer.string(self)
+    }
+}
+
+/// Types whose [InsertValue] holds no heap data, so converting a copy costs nothing.
+macro_rules! to_value_copy {
+    ($($t:ty),*) => {
+        $(
+            impl ToValue for $t {
+                fn to_value(&self) -> Result<InsertValue> {
+                    Ok((*self).into())
+                }
+            }
+        )*
+    };
+}
+
+to_value_copy!(
+    i8,
+    i16,
+    i32,
+    i64,
+    u8,
+    u16,
+    u32,
+    u64,
+    f32,
+    f64,
+    bool,
+    rust_decimal::Decimal,
+    jiff::civil::Date,
+    jiff::civil::DateTime,
+    jiff::Timestamp
+);
+
+impl ToValue for jiff::Zoned {
+    fn to_value(&self) -> Result<InsertValue> {
+        Ok(self.into())
+    }
+}
+
+impl ToValue for serde_json::Value {
+    fn to_value(&self) -> Result<InsertValue> {
+        Ok(self.into())
+    }
+}
+
+impl<T: ToValue> ToValue for [T] {
+    fn to_value(&self) -> Result<InsertValue> {
+        let values = self.iter().map(|v| Ok(v.to_value()?.0));
+        Ok(InsertValue(Value::Array(values.collect::<Result<_>>()?)))
+    }
+}
+
+impl<T: ToValue> ToValue for Vec<T> {
+    fn to_value(&self) -> Result<InsertValue> {
+        self.as_slice().to_value()
+    }
+}
+
+impl<K: ToValue, V: ToValue> ToValue for BTreeMap<K, V> {
+    fn to_value(&self) -> Result<InsertValue> {
+        map_value(self.iter())
+    }
+}
+
+impl<K: ToValue, V: ToValue, S> ToValue for HashMap<K, V, S> {
+    fn to_value(&self) -> Result<InsertValue> {
+        map_value(self.iter())
+    }
+}
+
+/// A `MAP` of `entries`.
+fn map_value<'a, K, V>(entries: impl Iterator<Item = (&'a K, &'a V)>) -> Result<InsertValue>
+where
+    K: ToValue + 'a,
+    V: ToValue + 'a,
+{
+    let entries = entries.map(|(k, v)| Ok((k.to_value()?.0, v.to_value()?.0)));
+    Ok(InsertValue(Value::Map(entries.collect::<Result<_>>()?)))
+}
@@ -0,0 +1,403 @@
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use futures::{Stream, StreamExt};
+use tokio::time::{timeout_at, Instant};
+
+use crate::error::Result;
+use crate::executor::{Executor, WriteStats};
+use crate::row::write_row;
+use crate::tables::{Insert, Statement, Table, Upsert};
+
+/// Controls when an [InsertSink] sends the rows it has received.
+///
+/// A statement is sent once it holds `max_rows` rows, once adding another row would take
+/// it over `max_bytes`, or `flush_interval` after its first row arrived, whichever comes
+/// first. A single row larger than `max_bytes` is sent on its own.
+#[derive(Debug, Clone)]
+pub struct SinkOptions {
+    max_rows: Option<usize>,
+    max_bytes: Option<usize>,
+    flush_interval: Option<Duration>,
+}
+
+impl Default for SinkOptions {
+    fn default() -> Self {
+        Self {
+            max_rows: Some(10_000),
+            max_bytes: Some(4 * 1024 * 1024),
+            flush_interval: Some(Duration::from_secs(5)),
+        }
+    }
+}
+
+impl SinkOptions {
+    /// Maximum number of rows per statement. `None` removes the limit.
+    pub fn max_rows(mut self, max_rows: impl Into<Option<usize>>) -> Self {
+        self.max_rows = max_rows.into();
+        self
+    }
+
+    /// Maximum size of a statement in bytes. `None` removes the limit.
+    pub fn max_bytes(mut self, max_bytes: impl Into<Option<usize>>) -> Self {
+        self.max_bytes = max_bytes.into();
+        self
+    }
+
+    /// Longest a row waits for more rows before it is sent. `None` waits until the statement
+    /// is full or the stream ends.
+    pub fn flush_interval(mut self, flush_interval: impl Into<Option<Duration>>) -> Self {
+        self.flush_interval = flush_interval.into();
+        self
+    }
+}
+
+/// The result of running an [InsertSink].
+#[derive(Debug)]
+pub struct SinkOutcome {
+    /// What the statements that succeeded wrote, added up from what the server reported
+    /// for each.
+    pub stats: WriteStats,
+    /// Number of statements that succeeded.
+    pub batches: usize,
+    /// The error of the statement that stopped the sink, if one failed.
+    pub result: Result<()>,
+}
+
+impl SinkOutcome {
+    pub fn is_ok(&self) -> bool {
+        self.result.is_ok()
+    }
+}
+
+/// Inserts rows as they arrive from a [Stream], without collecting them first.
+/// Created with [Insert::sink] or [Upsert::sink].
+///
+/// Rows are rendered into a statement as they are received and dropped, so only the
+/// statement being filled is held in memory. The stream is not polled while a statement is
+/// running, so a producer feeding the sink through a bounded channel, such as
+/// [futures::channel::mpsc::channel], waits for the sink once the channel is full.
+pub struct InsertSink<T> {
+    statement: Statement,
+    options: SinkOptions,
+    table: PhantomData<fn(T)>,
+}
+
+impl<T: Table> Insert<T> {
+    pub fn sink(self, options: SinkOptions) -> InsertSink<T> {
+        InsertSink {
+            statement: Statement::insert::<T>(),
+            options,
+            table: PhantomData,
+        }
+    }
+}
+
+impl<T: Table> Upsert<T> {
+    pub fn sink(self, options: SinkOptions) -> Result<InsertSink<T>> {
+        Ok(InsertSink {
+            statement: Statement::upsert::<T>()?,
+            options,
+            table: PhantomData,
+        })
+    }
+}
+
+impl<T: Table> InsertSink<T> {
+    /// Insert every row of `rows`, until the stream ends or a statement fails.
+    ///
+    /// Statements are sent one at a time, in the order the rows arrived. A failed statement
+    /// stops the sink: its rows and any the stream has not yet produced are not written.
+    /// A row that cannot be rendered, such as a variant that is not JSON, stops it too, once
+    /// the rows received before it have been sent. The outcome reports what was written.
+    pub async fn run<S>(&self, conn: &(impl Executor + ?Sized), rows: S) -> SinkOutcome
+    where
+        S: Stream<Item = T>,
+    {
+        let mut outcome = SinkOutcome {
+            stats: WriteStats::default(),
+            batches: 0,
+            result: Ok(()),
+        };
+        if let Err(e) = self.write(conn, rows, &mut outcome).await {
+            outcome.result = Err(e);
+        }
+        outcome
+    }
+
+    async fn write<S>(
+        &self,
+        conn: &(impl Executor + ?Sized),
+        rows: S,
+        outcome: &mut SinkOutcome,
+    ) -> Result<()>
+    where
+        S: Stream<Item = T>,
+    {
+        let dialect = conn.dialect();
+        let mut rows = std::pin::pin!(rows);
+        let mut pending = Pending::new(self.statement.prefix(dialect, conn.namespace()));
+        let mut rendered = String::new();
+
+        loop {
+            let next = match pending.deadline {
+                Some(deadline) => match timeout_at(deadline, rows.next()).await {
+                    Ok(next) => next,
+                    Err(_) => {
+                        pending.flush(conn, outcome).await?;
+                        continue;
+                    }
+                },
+                None => rows.next().await,
+            };
+            let Some(row) = next else {
+                break;
+            };
+
+            rendered.clear();
+            if let Err(e) = write_row(&mut rendered, dialect, &row) {
+                pending.flush(conn, outcome).await?;
+                return Err(e);
+            }
+
+            // +1 for the separating comma
+            if pending.rows > 0
+                && self
+                    .options
+                    .max_bytes
+                    .is_some_and(|max_bytes| pending.sql.len() + 1 + rendered.len() > max_bytes)
+            {
+                pending.flush(conn, outcome).await?;
+            }
+            pending.push(&rendered, self.options.flush_interval);
+
+            if self
+                .options
+                .max_rows
+                .is_some_and(|max_rows| pending.rows >= max_rows)
+            {
+                pending.flush(conn, outcome).await?;
+            }
+        }
+
+        pending.flush(conn, outcome).await
+    }
+}
+
+/// The statement an [InsertSink] is filling with rows.
+struct Pending {
+    sql: String,
+    /// Length of the statement text that comes before the rows.
+    prefix: usize,
+    rows: usize,
+    /// When the rows are sent even if more would fit.
+    deadline: Option<Instant>,
+}
+
+impl Pending {
+    fn new(prefix: String) -> Self {
+        Pending {
+            prefix: prefix.len(),
+            sql: prefix,
+            rows: 0,
+            deadline: None,
+        }
+    }
+
+    fn push(&mut self, row: &str, flush_interval: Option<Duration>) {
+        if self.rows == 0 {
+            self.deadline = flush_interval.map(|interval| Instant::now() + interval);
+        } else {
+            self.sql.push(',');
+        }
+        self.sql.push_str(row);
+        self.rows += 1;
+    }
+
+    /// Send the rows, if there are any, and start a new statement.
+    async fn flush(
+        &mut self,
+        conn: &(impl Executor + ?Sized),
+        outcome: &mut SinkOutcome,
+    ) -> Result<()> {
+        self.deadline = None;
+        if self.rows == 0 {
+            return Ok(());
+        }
+        let stats = conn.write(&self.sql).await?;
+        outcome.stats.add(&stats);
+        outcome.batches += 1;
+        self.rows = 0;
+        self.sql.truncate(self.prefix);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use futures::channel::mpsc;
+    use futures::SinkExt;
+
+    use super::*;
+    use crate::error::Error;
+    use crate::mock::MockConnection;
+    use crate::{create, insert, AirbendTable, Databend, Namespace};
+
+    #[derive(AirbendTable)]
+    #[airbend_table(table_name = "reading")]
+    struct Reading {
+        site_code: String,
+        value: f64,
+    }
+
+    fn readings(site_codes: &[&str]) -> Vec<Reading> {
+        site_codes
+            .iter()
+            .map(|site_code| Reading {
+                site_code: site_code.to_string(),
+                value: 1.5,
+            })
+            .collect()
+    }
+
+    fn three_readings() -> impl Stream<Item = Reading> {
+        futures::stream::iter(readings(&["MY1", "MY2", "MY3"]))
+    }
+
+    /// The size of the statement inserting `rows` on its own.
+    fn statement_bytes(rows: &[Reading]) -> usize {
+        insert()
+            .values(rows)
+            .sql(&Databend, &Namespace::default())
+            .unwrap()
+            .len()
+    }
+
+    /// The size of every insert the mock ran.
+    fn sent_bytes(conn: &MockConnection) -> Vec<usize> {
+        conn.executed()
+            .iter()
+            .filter(|sql| sql.starts_with("INSERT"))
+            .map(String::len)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn outcome_adds_up_the_stats_of_each_statement() {
+        let conn = MockConnection::new();
+        create::<Reading>(&conn).await.unwrap();
+        let sink = insert().sink(SinkOptions::default().max_rows(2));
+
+        let outcome = sink.run(&conn, three_readings()).await;
+        outcome.result.unwrap();
+        assert_eq!(outcome.batches, 2);
+        assert_eq!(outcome.stats.rows, 3);
+        assert_eq!(conn.rows("reading").len(), 3);
+    }
+
+    #[tokio::test]
+    async fn outcome_counts_statements_before_a_failure() {
+        let conn = MockConnection::new();
+        create::<Reading>(&conn).await.unwrap();
+        conn.fail_on("MY3", Error::Connection("connection reset".to_string()));
+        let sink = insert().sink(SinkOptions::default().max_rows(2));
+
+        let outcome = sink.run(&conn, three_readings()).await;
+        assert!(matches!(outcome.result, Err(Error::Connection(_))));
+        assert_eq!(outcome.batches, 1);
+        assert_eq!(outcome.stats.rows, 2);
+    }
+
+    #[tokio::test]
+    async fn splits_on_max_bytes() {
+        let rows = readings(&["MY1", "MY2", "MY3", "MY4", "MY5"]);
+        let max_bytes = statement_bytes(&rows[..2]);
+        let conn = MockConnection::new();
+        create::<Reading>(&conn).await.unwrap();
+        let sink = insert().sink(SinkOptions::default().max_rows(None).max_bytes(max_bytes));
+
+        let outcome = sink.run(&conn, futures::stream::iter(rows)).await;
+        outcome.result.unwrap();
+        assert_eq!(outcome.batches, 3);
+        assert_eq!(outcome.stats.rows, 5);
+        assert_eq!(
+            sent_bytes(&conn),
+            [max_bytes, max_bytes, statement_bytes(&readings(&["MY5"]))]
+        );
+    }
+
+    #[tokio::test]
+    async fn sends_an_oversized_row_alone() {
+        let long = "X".repeat(100);
+        let rows = readings(&["MY1", &long, "MY3", "MY4"]);
+        let expected = [
+            statement_bytes(&rows[..1]),
+            statement_bytes(&rows[1..2]),
+            statement_bytes(&rows[2..]),
+        ];
+        let max_bytes = expected[2];
+        let conn = MockConnection::new();
+        create::<Reading>(&conn).await.unwrap();
+        let sink = insert().sink(SinkOptions::default().max_rows(None).max_bytes(max_bytes));
+
+        let outcome = sink.run(&conn, futures::stream::iter(rows)).await;
+        outcome.result.unwrap();
+        assert_eq!(outcome.stats.rows, 4);
+        assert_eq!(sent_bytes(&conn), expected);
+        assert!(expected[1] > max_bytes);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn flushes_after_the_interval_while_the_stream_stalls() {
+        let conn = MockConnection::new();
+        create::<Reading>(&conn).await.unwrap();
+        let sink = insert().sink(SinkOptions::default().flush_interval(Duration::from_secs(5)));
+        let (mut sender, receiver) = mpsc::channel(1);
+        sender.send(readings(&["MY1"]).remove(0)).await.unwrap();
+
+        // The stream stays open, with no more rows, until the check has run
+        let check = async {
+            tokio::time::sleep(Duration::from_secs(6)).await;
+            let written = conn.rows("reading").len();
+            drop(sender);
+            written
+        };
+        let (outcome, written) = tokio::join!(sink.run(&conn, receiver), check);
+        assert_eq!(written, 1);
+        outcome.result.unwrap();
+        assert_eq!(outcome.batches, 1);
+    }
+
+    #[derive(AirbendTable)]
+    #[airbend_table(table_name = "grid")]
+    struct Grid {
+        site_code: String,
+        #[airbend_col(variant)]
+        cells: BTreeMap<(u8, u8), f64>,
+    }
+
+    #[tokio::test]
+    async fn sends_pending_rows_before_a_row_that_cannot_be_rendered() {
+        let conn = MockConnection::new();
+        create::<Grid>(&conn).await.unwrap();
+        let rows = [
+            Grid {
+                site_code: "MY1".to_string(),
+                cells: BTreeMap::new(),
+            },
+            Grid {
+                site_code: "MY2".to_string(),
+                cells: BTreeMap::from([((0, 0), 1.5)]),
+            },
+        ];
+        let sink = insert().sink(SinkOptions::default());
+
+        let outcome = sink.run(&conn, futures::stream::iter(rows)).await;
+        assert!(matches!(outcome.result, Err(Error::Conversion(_))));
+        assert_eq!(outcome.batches, 1);
+        assert_eq!(outcome.stats.rows, 1);
+        assert_eq!(conn.rows("grid").len(), 1);
+    }
+}
@@ -0,0 +1,147 @@
+//! Run tables against a local SQLite database. Enabled with the `sqlite` feature.
+//!
+//! ```ignore
+//! let conn = SqliteConnection::open_in_memory()?;
+//! create::<Reading>(&conn).await?;
+//! insert::<Reading>().values(readings).execute(&conn).await?;
+//! ```
+//!
+//! Statements are rendered with the [Sqlite] dialect. Calls block the current thread while
+//! SQLite runs them, which is fine for local development and tests.
+
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use databend_driver::{NumberValue, Value};
+use rusqlite::types::ValueRef;
+
+use crate::dialect::{Dialect, Sqlite};
+use crate::error::{Error, Result};
+use crate::executor::{Executor, WriteStats};
+use crate::load::LoadFormat;
+use crate::types::value_from_text;
+
+/// An [Executor] backed by a SQLite database.
+pub struct SqliteConnection {
+    conn: Mutex<rusqlite::Connection>,
+}
+
+impl SqliteConnection {
+    /// Open (or create) the database at `path`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        rusqlite::Connection::open(path)
+            .map(Self::from)
+            .map_err(connection_error)
+    }
+
+    /// A new database that only lives as long as the connection.
+    pub fn open_in_memory() -> Result<Self> {
+        rusqlite::Connection::open_in_memory()
+            .map(Self::from)
+            .map_err(connection_error)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, rusqlite::Connection> {
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A database that cannot be opened is reported as a connection failure.
+fn connection_error(error: rusqlite::Error) -> Error {
+    Error::Connection(error.to_string())
+}
+
+impl From<rusqlite::Connection> for SqliteConnection {
+    fn from(conn: rusqlite::Connection) -> Self {
+        Self {
+            conn: Mutex::new(conn),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Executor for SqliteConnection {
+    async fn exec(&self, sql: &str) -> Result<i64> {
+        let rows = self.lock().execute(sql, [])?;
+        Ok(rows as i64)
+    }
+
+    async fn query(&self, sql: &str) -> Result<Vec<Vec<Value>>> {
+        let conn = self.lock();
+        let mut statement = conn.prepare(sql)?;
+        // Declared column types decide how stored values are converted back
+        let types: Vec<_> = statement
+            .columns()
+            .iter()
+            .map(|column| column.decl_type().unwrap_or_default().to_ascii_uppercase())
+            .collect();
+
+        let mut rows = statement.query([])?;
+        let mut results = vec![];
+        while let Some(row) = rows.next()? {
+            let mut values = Vec::with_capacity(types.len());
+            for (i, data_type) in types.iter().enumerate() {
+                values.push(from_sqlite(row.get_ref(i)?, data_type)?);
+            }
+            results.push(values);
+        }
+        Ok(results)
+    }
+
+    async fn load(&self, _sql: &str, _format: LoadFormat, _data: Arc<[u8]>) -> Result<WriteStats> {
+        Err(Error::Generation(
+            "SQLite does not support bulk loads, use Insert::values instead".to_string(),
+        ))
+    }
+
+    fn dialect(&self) -> &dyn Dialect {
+        &Sqlite
+    }
+}
+
+/// Convert a stored value into the [Value] databend would return for `data_type`.
+fn from_sqlite(value: ValueRef<'_>, data_type: &str) -> Result<Value> {
+    let text = match value {
+        ValueRef::Null => return Ok(Value::Null),
+        ValueRef::Blob(bytes) => return Ok(Value::Binary(bytes.to_vec())),
+        ValueRef::Integer(v) => v.to_string(),
+        ValueRef::Real(v) => v.to_string(),
+        ValueRef::Text(text) => String::from_utf8(text.to_vec())?,
+    };
+
+    match data_type {
+        // Expressions have no declared type, so go by the storage class
+        "" => Ok(match value {
+            ValueRef::Integer(v) => Value::Number(NumberValue::Int64(v)),
+            ValueRef::Real(v) => Value::Number(NumberValue::Float64(v)),
+            _ => Value::String(text),
+        }),
+        "TEXT" => Ok(Value::String(text)),
+        "JSON" => Ok(from_json(serde_json::from_str(&text)?)),
+        data_type => value_from_text(text, data_type),
+    }
+}
+
+/// Nested values are stored as JSON. Elements come back as strings, numbers and booleans,
+/// so types like timestamps inside an `ARRAY` are not restored.
+fn from_json(json: serde_json::Value) -> Value {
+    use serde_json::Value as Json;
+
+    match json {
+        Json::Null => Value::Null,
+        Json::Bool(v) => Value::Boolean(v),
+        Json::Number(v) => match (v.as_i64(), v.as_u64()) {
+            (Some(v), _) => Value::Number(NumberValue::Int64(v)),
+            (None, Some(v)) => Value::Number(NumberValue::UInt64(v)),
+            _ => Value::Number(NumberValue::Float64(v.as_f64().unwrap_or(f64::NAN))),
+        },
+        Json::String(s) => Value::String(s),
+        Json::Array(values) => Value::Array(values.into_iter().map(from_json).collect()),
+        Json::Object(entries) => Value::Map(
+            entries
+                .into_iter()
+                .map(|(key, value)| (Value::String(key), from_json(value)))
+                .collect(),
+        ),
+    }
+}
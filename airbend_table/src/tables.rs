@@ -1,73 +1,249 @@
-use databend_driver::{Connection, NumberValue, Value};
+use databend_driver::{NumberValue, Value};
+use databend_driver_core::schema::DecimalSize;
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
 
 use std::marker::PhantomData;
 
+use crate::dialect::{Databend, Dialect, Literal};
+use crate::error::{Error, Result};
+use crate::executor::{Executor, WriteStats};
+use crate::namespace::Namespace;
+use crate::registry::TableDef;
+use crate::row::{write_row, RowWriter};
+use crate::types::ColumnType;
+
 pub struct Field {
     pub name: &'static str,
-    pub data_type: &'static str,
+    pub data_type: ColumnType,
     pub nullable: bool,
+    /// Part of the key used to match existing rows in an [upsert]. Key columns should not be
+    /// nullable, as a NULL key never matches an existing row.
+    pub key: bool,
+    /// Stored with the column. The derive takes this from the field's doc comment.
+    pub comment: Option<&'static str>,
+}
+
+/// Table level settings used when creating a table. Databend only; other dialects ignore them.
+#[derive(Debug, Clone, Default)]
+pub struct TableOptions {
+    /// Columns the table is clustered by, so range queries on them can skip blocks.
+    pub cluster_by: Vec<&'static str>,
+    /// Stored with the table. The derive takes this from the struct's doc comment.
+    pub comment: Option<&'static str>,
+    /// Create a transient table, which keeps no history for time travel.
+    pub transient: bool,
+    /// Other table options such as `compression` or `bloom_index_columns`,
+    /// written as `name = 'value'`.
+    pub options: Vec<(&'static str, &'static str)>,
 }
 
 pub trait Table {
     fn name() -> &'static str;
     fn schema() -> Vec<Field>;
-    fn to_row(self) -> Vec<InsertValue>;
+    /// The row's values, in the order of [Table::schema]. Fails with [Error::Conversion] if
+    /// a value cannot be encoded.
+    fn to_row(&self) -> Result<Vec<InsertValue>>;
+
+    /// Write the row's values into an insert statement, in the order of [Table::schema].
+    /// The derive writes fields straight from `self`; the default goes through [Table::to_row].
+    fn write_row(&self, row: &mut RowWriter<'_>) -> Result<()> {
+        for value in self.to_row()? {
+            row.value(&value)?;
+        }
+        Ok(())
+    }
+
+    fn options() -> TableOptions {
+        TableOptions::default()
+    }
+
+    /// The database the table is created in. `None` (the default) uses the connection's
+    /// current database. A [Namespace] database takes precedence.
+    fn database() -> Option<&'static str> {
+        None
+    }
 }
 
-pub async fn create<T: Table>(conn: &dyn Connection) -> anyhow::Result<()> {
-    let fields: Vec<_> = T::schema()
-        .iter()
-        .map(|field| {
-            let nullable = if field.nullable { "NULL" } else { "NOT NULL" };
-            format!("{} {} {}", field.name, field.data_type, nullable)
-        })
-        .collect();
+/// Columns shared by several tables, embedded in a table struct with `#[airbend_col(flatten)]`.
+/// Derived with [crate::AirbendColumns], which implements [crate::FromRow] too.
+pub trait Columns {
+    /// The number of columns, including those of nested flattened fields.
+    const COLUMN_COUNT: usize;
 
-    let fields = fields.join(", ");
+    fn columns() -> Vec<Field>;
+    fn to_values(&self) -> Result<Vec<InsertValue>>;
+    fn write_values(&self, row: &mut RowWriter<'_>) -> Result<()>;
+}
 
-    let query = format!("CREATE TABLE IF NOT EXISTS {} ({});", T::name(), fields);
-    conn.exec(&query).await?;
+/// Create `T`'s table if it does not exist, first creating its database if it has one.
+pub async fn create<T: Table>(conn: &(impl Executor + ?Sized)) -> Result<()> {
+    let table = TableDef::of::<T>();
+    let (dialect, namespace) = (conn.dialect(), conn.namespace());
+    if let Some(sql) = namespace
+        .database_of(&table)
+        .and_then(|database| dialect.create_database(database))
+    {
+        conn.exec(&sql).await?;
+    }
+    conn.exec(&table.create_sql(dialect, namespace)).await?;
     Ok(())
 }
 
-pub struct Query(String);
+/// Remove every row from `T`'s table, keeping the table itself.
+pub async fn truncate<T: Table>(conn: &(impl Executor + ?Sized)) -> Result<()> {
+    let table = conn.namespace().table(conn.dialect(), &TableDef::of::<T>());
+    conn.exec(&conn.dialect().truncate_table(&table)).await?;
+    Ok(())
+}
 
-impl Query {
-    pub async fn execute(&self, conn: &dyn Connection) -> anyhow::Result<()> {
-        conn.exec(&self.0).await?;
-        Ok(())
+/// Drop `T`'s table if it exists. Named so a glob import does not shadow [std::mem::drop].
+pub async fn drop_table<T: Table>(conn: &(impl Executor + ?Sized)) -> Result<()> {
+    let table = conn.namespace().table(conn.dialect(), &TableDef::of::<T>());
+    conn.exec(&conn.dialect().drop_table(&table)).await?;
+    Ok(())
+}
+
+/// An insert of rows into a table. The SQL is rendered when the query is run, in the
+/// dialect of the connection it runs on, straight from the rows it was given. Rows can be
+/// owned or borrowed, in which case the query borrows them for `'a`.
+pub struct Query<'a> {
+    statement: Statement,
+    rows: Box<dyn WriteRows + 'a>,
+}
+
+impl<'a> Query<'a> {
+    fn new<V, T>(statement: Statement, values: V) -> Self
+    where
+        V: AsRef<[T]> + Send + Sync + 'a,
+        T: Table + 'a,
+    {
+        Query {
+            statement,
+            rows: Box::new(Rows {
+                values,
+                table: PhantomData,
+            }),
+        }
+    }
+
+    /// The SQL for this query in `dialect`, writing to the table's name in `namespace`.
+    /// Fails if a row's values cannot be encoded.
+    pub fn sql(&self, dialect: &dyn Dialect, namespace: &Namespace) -> Result<String> {
+        let mut sql = self.statement.prefix(dialect, namespace);
+        self.rows.write_rows(&mut sql, dialect)?;
+        Ok(sql)
+    }
+
+    /// Run the insert, returning what the server reports it wrote.
+    pub async fn execute(&self, conn: &(impl Executor + ?Sized)) -> Result<WriteStats> {
+        conn.write(&self.sql(conn.dialect(), conn.namespace())?)
+            .await
     }
 }
 
-pub struct Insert<T> {
-    data_type: PhantomData<T>,
+/// The kind of statement used to write rows.
+pub(crate) enum Statement {
+    Insert {
+        table: TableDef,
+        columns: Vec<&'static str>,
+    },
+    Upsert {
+        table: TableDef,
+        columns: Vec<&'static str>,
+        keys: Vec<&'static str>,
+    },
 }
 
-impl<T: Table> Insert<T> {
-    pub fn values(self, values: Vec<T>) -> Query {
-        use std::io::Write;
-        let mut buff = vec![];
-        let mut row_iter = values.into_iter().peekable();
-        while let Some(r) = row_iter.next() {
-            write!(&mut buff, "(").unwrap();
-            let mut row = r.to_row().into_iter().peekable();
-            while let Some(element) = row.next() {
-                write!(buff, "{}", element).unwrap();
-                if row.peek().is_some() {
-                    write!(buff, ", ").unwrap();
-                }
+impl Statement {
+    pub(crate) fn insert<T: Table>() -> Self {
+        Statement::Insert {
+            table: TableDef::of::<T>(),
+            columns: columns::<T>(),
+        }
+    }
+
+    /// An upsert keyed on the key columns of `T`. Fails if there are none.
+    pub(crate) fn upsert<T: Table>() -> Result<Self> {
+        let keys: Vec<_> = T::schema()
+            .iter()
+            .filter(|field| field.key)
+            .map(|field| field.name)
+            .collect();
+        if keys.is_empty() {
+            return Err(Error::Generation(format!(
+                "Table {} has no key columns. Mark them with #[airbend_col(key)]",
+                T::name()
+            )));
+        }
+        Ok(Statement::Upsert {
+            table: TableDef::of::<T>(),
+            columns: columns::<T>(),
+            keys,
+        })
+    }
+
+    /// The start of the statement, up to and including `VALUES `. Columns are always listed,
+    /// so rows are written correctly even if the table's columns are in a different order.
+    pub(crate) fn prefix(&self, dialect: &dyn Dialect, namespace: &Namespace) -> String {
+        match self {
+            Statement::Insert { table, columns } => {
+                dialect.insert_prefix(&namespace.table(dialect, table), columns)
             }
-            write!(buff, ")").unwrap();
-            if row_iter.peek().is_some() {
-                write!(buff, ",").unwrap();
+            Statement::Upsert {
+                table,
+                columns,
+                keys,
+            } => dialect.upsert_prefix(&namespace.table(dialect, table), columns, keys),
+        }
+    }
+}
+
+fn columns<T: Table>() -> Vec<&'static str> {
+    T::schema().iter().map(|field| field.name).collect()
+}
+
+/// The rows of a [Query], whatever their table type.
+trait WriteRows: Send + Sync {
+    fn write_rows(&self, sql: &mut String, dialect: &dyn Dialect) -> Result<()>;
+}
+
+/// Rows of table `T`, held as anything that can be viewed as a slice of them.
+struct Rows<V, T> {
+    values: V,
+    table: PhantomData<fn() -> T>,
+}
+
+impl<V, T> WriteRows for Rows<V, T>
+where
+    V: AsRef<[T]> + Send + Sync,
+    T: Table,
+{
+    fn write_rows(&self, sql: &mut String, dialect: &dyn Dialect) -> Result<()> {
+        for (i, row) in self.values.as_ref().iter().enumerate() {
+            if i > 0 {
+                sql.push(',');
             }
+            write_row(sql, dialect, row)?;
         }
+        Ok(())
+    }
+}
 
-        Query(format!(
-            "INSERT INTO {} VALUES {}",
-            T::name(),
-            String::from_utf8(buff).expect("Must be valid utf-8")
-        ))
+pub struct Insert<T> {
+    data_type: PhantomData<T>,
+}
+
+impl<T: Table> Insert<T> {
+    /// Insert `values`: a `Vec<T>` the query takes over, or a slice such as `&rows` that it
+    /// borrows, so the rows can still be used afterwards.
+    pub fn values<'a, V>(self, values: V) -> Query<'a>
+    where
+        V: AsRef<[T]> + Send + Sync + 'a,
+        T: 'a,
+    {
+        Query::new(Statement::insert::<T>(), values)
     }
 }
 
@@ -77,27 +253,71 @@ pub fn insert<T: Table>() -> Insert<T> {
     }
 }
 
-pub struct InsertValue(Value);
+pub struct Upsert<T> {
+    data_type: PhantomData<T>,
+}
+
+impl<T: Table> Upsert<T> {
+    /// Insert `values`, replacing any existing rows with the same key columns. Like
+    /// [Insert::values], takes a `Vec<T>` or borrows a slice. Fails if `T` has no key columns.
+    pub fn values<'a, V>(self, values: V) -> Result<Query<'a>>
+    where
+        V: AsRef<[T]> + Send + Sync + 'a,
+        T: 'a,
+    {
+        Ok(Query::new(Statement::upsert::<T>()?, values))
+    }
+}
+
+/// Insert rows, replacing existing rows that match on the columns marked `#[airbend_col(key)]`.
+/// Re-inserting the same rows is idempotent.
+pub fn upsert<T: Table>() -> Upsert<T> {
+    Upsert {
+        data_type: PhantomData,
+    }
+}
+
+pub struct InsertValue(pub(crate) Value);
 
+/// Renders the value as a databend literal.
 impl std::fmt::Display for InsertValue {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match &self.0 {
-            // Some types need to be in quotes
-            Value::Timestamp(_)
-            | Value::Date(_)
-            | Value::String(_)
-            | Value::Bitmap(_)
-            | Value::Variant(_)
-            | Value::Geometry(_) => {
-                write!(f, "'")?;
-                self.0.fmt(f)?;
-                write!(f, "'")
-            }
-            _ => self.0.fmt(f),
+        Databend.write_literal(f, &self.0)
+    }
+}
+
+impl InsertValue {
+    /// The value as a literal of `dialect`.
+    pub(crate) fn literal<'a>(&'a self, dialect: &'a dyn Dialect) -> Literal<'a> {
+        Literal {
+            dialect,
+            value: &self.0,
         }
     }
 }
 
+/// A `TUPLE` of `elements`. Used by code generated by the `AirbendTuple` derive.
+pub fn tuple(elements: Vec<InsertValue>) -> InsertValue {
+    InsertValue(Value::Tuple(
+        elements.into_iter().map(|element| element.0).collect(),
+    ))
+}
+
+/// `value` as JSON for a `VARIANT` column, for fields marked `#[airbend_col(variant)]`.
+/// In a nullable column, values that serialize to `null` (such as `None`) are stored as `NULL`.
+///
+/// Fails with [Error::Conversion] if `value` cannot be serialized as JSON, e.g. a map with
+/// non-string keys.
+pub fn to_variant<T: serde::Serialize + ?Sized>(value: &T, nullable: bool) -> Result<InsertValue> {
+    let json = serde_json::to_value(value)
+        .map_err(|e| Error::Conversion(format!("Cannot store value as a VARIANT: {}", e)))?;
+    if nullable && json.is_null() {
+        Ok(InsertValue(Value::Null))
+    } else {
+        Ok(InsertValue(Value::Variant(json.to_string())))
+    }
+}
+
 impl From<Value> for InsertValue {
     fn from(value: Value) -> Self {
         InsertValue(value)
@@ -131,9 +351,46 @@ impl From<&String> for InsertValue {
     }
 }
 
-impl From<u32> for InsertValue {
-    fn from(value: u32) -> Self {
-        InsertValue(Value::Number(NumberValue::UInt32(value)))
+impl From<Cow<'_, str>> for InsertValue {
+    fn from(value: Cow<'_, str>) -> Self {
+        InsertValue(Value::String(value.into_owned()))
+    }
+}
+
+macro_rules! insert_value_from_number {
+    ($($t:ty => $variant:ident),*) => {
+        $(
+            impl From<$t> for InsertValue {
+                fn from(value: $t) -> Self {
+                    InsertValue(Value::Number(NumberValue::$variant(value)))
+                }
+            }
+        )*
+    };
+}
+
+insert_value_from_number!(
+    i8 => Int8,
+    i16 => Int16,
+    i32 => Int32,
+    i64 => Int64,
+    u8 => UInt8,
+    u16 => UInt16,
+    u32 => UInt32,
+    u64 => UInt64
+);
+
+impl From<bool> for InsertValue {
+    fn from(value: bool) -> Self {
+        InsertValue(Value::Boolean(value))
+    }
+}
+
+impl From<jiff::civil::Date> for InsertValue {
+    fn from(value: jiff::civil::Date) -> Self {
+        // Databend stores dates as days since the unix epoch
+        let days = (value - jiff::civil::date(1970, 1, 1)).get_days();
+        InsertValue(Value::Date(days))
     }
 }
 
@@ -143,8 +400,212 @@ impl From<jiff::Timestamp> for InsertValue {
     }
 }
 
+/// Civil datetimes are taken to be in UTC.
+impl From<jiff::civil::DateTime> for InsertValue {
+    fn from(value: jiff::civil::DateTime) -> Self {
+        let timestamp = value
+            .to_zoned(jiff::tz::TimeZone::UTC)
+            .expect("every civil datetime can be represented in UTC")
+            .timestamp();
+        timestamp.into()
+    }
+}
+
+impl From<jiff::Zoned> for InsertValue {
+    fn from(value: jiff::Zoned) -> Self {
+        value.timestamp().into()
+    }
+}
+
+impl From<&jiff::Zoned> for InsertValue {
+    fn from(value: &jiff::Zoned) -> Self {
+        value.timestamp().into()
+    }
+}
+
 impl From<f64> for InsertValue {
     fn from(value: f64) -> Self {
         InsertValue(Value::Number(NumberValue::Float64(value)))
     }
 }
+
+impl From<f32> for InsertValue {
+    fn from(value: f32) -> Self {
+        InsertValue(Value::Number(NumberValue::Float32(value)))
+    }
+}
+
+impl From<rust_decimal::Decimal> for InsertValue {
+    fn from(value: rust_decimal::Decimal) -> Self {
+        // rust_decimal holds at most 28 significant digits, which always fits DECIMAL(38, s)
+        let size = DecimalSize {
+            precision: 38,
+            scale: value.scale() as u8,
+        };
+        InsertValue(Value::Number(NumberValue::Decimal128(
+            value.mantissa(),
+            size,
+        )))
+    }
+}
+
+impl<T: Into<InsertValue>> From<Vec<T>> for InsertValue {
+    fn from(value: Vec<T>) -> Self {
+        InsertValue(Value::Array(
+            value.into_iter().map(|v| v.into().0).collect(),
+        ))
+    }
+}
+
+impl<K: Into<InsertValue>, V: Into<InsertValue>> From<BTreeMap<K, V>> for InsertValue {
+    fn from(value: BTreeMap<K, V>) -> Self {
+        InsertValue(Value::Map(
+            value
+                .into_iter()
+                .map(|(k, v)| (k.into().0, v.into().0))
+                .collect(),
+        ))
+    }
+}
+
+impl<K: Into<InsertValue>, V: Into<InsertValue>, S> From<HashMap<K, V, S>> for InsertValue {
+    fn from(value: HashMap<K, V, S>) -> Self {
+        InsertValue(Value::Map(
+            value
+                .into_iter()
+                .map(|(k, v)| (k.into().0, v.into().0))
+                .collect(),
+        ))
+    }
+}
+
+/// JSON is sent as text, which databend parses into a `VARIANT`.
+impl From<serde_json::Value> for InsertValue {
+    fn from(value: serde_json::Value) -> Self {
+        InsertValue(Value::Variant(value.to_string()))
+    }
+}
+
+impl From<&serde_json::Value> for InsertValue {
+    fn from(value: &serde_json::Value) -> Self {
+        InsertValue(Value::Variant(value.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use databend_driver::Value;
+
+    use super::*;
+    use crate::mock::MockConnection;
+    use crate::{drop_table, insert, select, AirbendTable, Databend, Error, Namespace, Sqlite};
+
+    // The table and every column are reserved words in databend and SQLite
+    #[derive(AirbendTable)]
+    #[airbend_table(table_name = "order")]
+    struct Order {
+        date: jiff::civil::Date,
+        value: f64,
+        select: Option<String>,
+    }
+
+    #[test]
+    fn quotes_reserved_words() {
+        let namespace = Namespace::default();
+        let table = TableDef::of::<Order>();
+        let rows = [Order {
+            date: jiff::civil::date(2024, 1, 1),
+            value: 1.5,
+            select: None,
+        }];
+
+        assert_eq!(
+            table.create_sql(&Databend, &namespace),
+            "CREATE TABLE IF NOT EXISTS `order` (`date` DATE NOT NULL, `value` DOUBLE NOT NULL, \
+             `select` VARCHAR NULL);"
+        );
+        assert_eq!(
+            insert().values(&rows).sql(&Databend, &namespace).unwrap(),
+            "INSERT INTO `order` (`date`, `value`, `select`) VALUES ('2024-01-01', 1.5, NULL)"
+        );
+        assert_eq!(
+            table.create_sql(&Sqlite, &namespace),
+            "CREATE TABLE IF NOT EXISTS \"order\" (\"date\" DATE NOT NULL, \"value\" DOUBLE NOT NULL, \
+             \"select\" TEXT NULL);"
+        );
+        assert_eq!(
+            insert().values(&rows).sql(&Sqlite, &namespace).unwrap(),
+            "INSERT INTO \"order\" (\"date\", \"value\", \"select\") VALUES ('2024-01-01', 1.5, NULL)"
+        );
+    }
+
+    #[derive(AirbendTable)]
+    #[airbend_table(table_name = "grid")]
+    struct Grid {
+        #[airbend_col(variant)]
+        cells: BTreeMap<(u8, u8), f64>,
+    }
+
+    #[test]
+    fn variant_that_is_not_json_fails_to_convert() {
+        let rows = [Grid {
+            cells: BTreeMap::from([((0, 0), 1.5)]),
+        }];
+
+        let error = insert()
+            .values(&rows)
+            .sql(&Databend, &Namespace::default())
+            .unwrap_err();
+        assert!(matches!(error, Error::Conversion(_)), "{error}");
+    }
+
+    /// A table whose only value is one no date type can hold.
+    struct OutOfRange;
+
+    impl Table for OutOfRange {
+        fn name() -> &'static str {
+            "out_of_range"
+        }
+
+        fn schema() -> Vec<Field> {
+            vec![Field {
+                name: "time",
+                data_type: ColumnType::Timestamp,
+                nullable: false,
+                key: false,
+                comment: None,
+            }]
+        }
+
+        fn to_row(&self) -> Result<Vec<InsertValue>> {
+            Ok(vec![InsertValue(Value::Timestamp(i64::MAX))])
+        }
+    }
+
+    #[test]
+    fn values_sqlite_cannot_write_fail_to_convert() {
+        let namespace = Namespace::default();
+        let error = insert()
+            .values([OutOfRange])
+            .sql(&Sqlite, &namespace)
+            .unwrap_err();
+        assert!(matches!(error, Error::Conversion(_)), "{error}");
+
+        let error = select::<Order>()
+            .where_gt("date", InsertValue(Value::Timestamp(i64::MAX)))
+            .sql(&Sqlite, &namespace)
+            .unwrap_err();
+        assert!(matches!(error, Error::Conversion(_)), "{error}");
+    }
+
+    #[tokio::test]
+    async fn drops_tables_through_the_dialect() {
+        let conn = MockConnection::new();
+        create::<Order>(&conn).await.unwrap();
+        drop_table::<Order>(&conn).await.unwrap();
+        assert_eq!(conn.executed()[1], "DROP TABLE IF EXISTS `order`");
+        assert!(conn.tables().is_empty());
+    }
+}
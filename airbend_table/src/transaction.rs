@@ -0,0 +1,257 @@
+use std::future::Future;
+use std::sync::Arc;
+
+use databend_driver::Value;
+
+use crate::dialect::Dialect;
+use crate::error::Result;
+use crate::executor::{Executor, WriteStats};
+use crate::load::LoadFormat;
+use crate::namespace::Namespace;
+
+/// A transaction open on a connection, passed to the closure given to [transaction].
+///
+/// Implements [Executor], so the usual builders run inside the transaction:
+/// `insert().values(&rows).execute(&tx)`. Statements run in the connection's dialect and
+/// [Namespace].
+pub struct Transaction<'c, C: ?Sized> {
+    conn: &'c C,
+}
+
+/// Run `f` in a transaction on `conn`, committing if it succeeds and rolling back if it
+/// returns an error, so the statements it runs are written as one unit.
+///
+/// ```ignore
+/// transaction(&conn, |tx| async move {
+///     delete::<Reading>().where_eq("site_code", "MY1").execute(&tx).await?;
+///     insert().values(&readings).execute(&tx).await?;
+///     Ok(())
+/// })
+/// .await?;
+/// ```
+///
+/// If the commit fails the transaction is rolled back and the commit's error returned. If the
+/// rollback after an error fails too, `f`'s error is returned. A transaction belongs to the
+/// connection's session: statements sent through `conn` by other tasks while it is open run
+/// inside it, so give concurrent transactions their own connections. Dropping the returned
+/// future before it completes leaves the transaction open.
+///
+/// A rollback undoes inserts, upserts and deletes. Whether it also undoes [crate::create]
+/// depends on the database: SQLite's does.
+pub async fn transaction<'c, C, F, Fut, R>(conn: &'c C, f: F) -> Result<R>
+where
+    C: Executor + ?Sized,
+    F: FnOnce(Transaction<'c, C>) -> Fut,
+    Fut: Future<Output = Result<R>>,
+{
+    conn.exec("BEGIN").await?;
+    match f(Transaction { conn }).await {
+        Ok(value) => match conn.exec("COMMIT").await {
+            Ok(_) => Ok(value),
+            Err(e) => {
+                // Some databases keep the transaction open when a commit fails
+                let _ = conn.exec("ROLLBACK").await;
+                Err(e)
+            }
+        },
+        Err(e) => {
+            let _ = conn.exec("ROLLBACK").await;
+            Err(e)
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<C> Executor for Transaction<'_, C>
+where
+    C: Executor + ?Sized,
+{
+    async fn exec(&self, sql: &str) -> Result<i64> {
+        self.conn.exec(sql).await
+    }
+
+    async fn query(&self, sql: &str) -> Result<Vec<Vec<Value>>> {
+        self.conn.query(sql).await
+    }
+
+    async fn write(&self, sql: &str) -> Result<WriteStats> {
+        self.conn.write(sql).await
+    }
+
+    async fn load(&self, sql: &str, format: LoadFormat, data: Arc<[u8]>) -> Result<WriteStats> {
+        self.conn.load(sql, format, data).await
+    }
+
+    fn dialect(&self) -> &dyn Dialect {
+        self.conn.dialect()
+    }
+
+    fn namespace(&self) -> &Namespace {
+        self.conn.namespace()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::mock::MockConnection;
+    use crate::{create, insert, select, AirbendTable};
+
+    #[derive(AirbendTable, Debug, PartialEq)]
+    #[airbend_table(table_name = "reading")]
+    struct Reading {
+        #[airbend_col(key)]
+        site_code: String,
+        value: f64,
+    }
+
+    fn readings() -> Vec<Reading> {
+        vec![
+            Reading {
+                site_code: "MY1".to_string(),
+                value: 1.5,
+            },
+            Reading {
+                site_code: "MY2".to_string(),
+                value: 2.5,
+            },
+        ]
+    }
+
+    /// Insert the readings, then fail with a generation error if `fail` is set.
+    async fn write(conn: &(impl Executor + ?Sized), fail: bool) -> Result<()> {
+        transaction(conn, |tx| async move {
+            insert().values(readings()).execute(&tx).await?;
+            if fail {
+                return Err(Error::Generation("closure failed".to_string()));
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// The first keyword of every statement the mock ran.
+    fn statements(conn: &MockConnection) -> Vec<String> {
+        conn.executed()
+            .iter()
+            .map(|sql| {
+                sql.split_whitespace()
+                    .next()
+                    .unwrap_or_default()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn commits_when_the_closure_succeeds() {
+        let conn = MockConnection::new();
+        create::<Reading>(&conn).await.unwrap();
+
+        write(&conn, false).await.unwrap();
+        assert_eq!(statements(&conn), ["CREATE", "BEGIN", "INSERT", "COMMIT"]);
+        assert!(!conn.in_transaction());
+        assert_eq!(select::<Reading>().fetch(&conn).await.unwrap(), readings());
+    }
+
+    #[tokio::test]
+    async fn rolls_back_when_the_closure_fails() {
+        let conn = MockConnection::new();
+        create::<Reading>(&conn).await.unwrap();
+
+        let error = write(&conn, true).await.unwrap_err();
+        assert!(matches!(error, Error::Generation(_)), "{error}");
+        assert_eq!(statements(&conn), ["CREATE", "BEGIN", "INSERT", "ROLLBACK"]);
+        assert!(!conn.in_transaction());
+        assert!(conn.rows("reading").is_empty());
+    }
+
+    #[tokio::test]
+    async fn failed_commit_rolls_back_and_returns_its_error() {
+        let conn = MockConnection::new();
+        create::<Reading>(&conn).await.unwrap();
+        conn.fail_on(
+            "COMMIT",
+            Error::Server {
+                code: Some(1001),
+                message: "commit failed".to_string(),
+            },
+        );
+
+        let error = write(&conn, false).await.unwrap_err();
+        assert!(
+            matches!(
+                error,
+                Error::Server {
+                    code: Some(1001),
+                    ..
+                }
+            ),
+            "{error}"
+        );
+        assert_eq!(
+            statements(&conn),
+            ["CREATE", "BEGIN", "INSERT", "COMMIT", "ROLLBACK"]
+        );
+        assert!(!conn.in_transaction());
+        assert!(conn.rows("reading").is_empty());
+    }
+
+    #[cfg(feature = "sqlite")]
+    mod sqlite {
+        use super::*;
+        use crate::sqlite::SqliteConnection;
+
+        #[tokio::test]
+        async fn commits_when_the_closure_succeeds() {
+            let conn = SqliteConnection::open_in_memory().unwrap();
+            create::<Reading>(&conn).await.unwrap();
+
+            write(&conn, false).await.unwrap();
+            assert_eq!(select::<Reading>().fetch(&conn).await.unwrap(), readings());
+        }
+
+        #[tokio::test]
+        async fn rolls_back_when_the_closure_fails() {
+            let conn = SqliteConnection::open_in_memory().unwrap();
+            create::<Reading>(&conn).await.unwrap();
+
+            let error = write(&conn, true).await.unwrap_err();
+            assert!(matches!(error, Error::Generation(_)), "{error}");
+            assert!(select::<Reading>().fetch(&conn).await.unwrap().is_empty());
+        }
+
+        #[tokio::test]
+        async fn failed_commit_rolls_back_and_returns_its_error() {
+            let conn = SqliteConnection::open_in_memory().unwrap();
+            create::<Reading>(&conn).await.unwrap();
+            // A deferred foreign key is only checked on COMMIT, which then fails and leaves
+            // the transaction open
+            conn.exec("PRAGMA foreign_keys = ON").await.unwrap();
+            conn.exec("CREATE TABLE site (code TEXT PRIMARY KEY)")
+                .await
+                .unwrap();
+            conn.exec(
+                "CREATE TABLE visit (site TEXT REFERENCES site (code) DEFERRABLE INITIALLY DEFERRED)",
+            )
+            .await
+            .unwrap();
+
+            let error = transaction(&conn, |tx| async move {
+                insert().values(readings()).execute(&tx).await?;
+                tx.exec("INSERT INTO visit VALUES ('MY1')").await?;
+                Ok(())
+            })
+            .await
+            .unwrap_err();
+            assert!(
+                matches!(&error, Error::Server { message, .. } if message.contains("FOREIGN KEY")),
+                "{error}"
+            );
+            assert!(select::<Reading>().fetch(&conn).await.unwrap().is_empty());
+            // The rollback closed the transaction, so a new one can begin
+            write(&conn, false).await.unwrap();
+        }
+    }
+}
@@ -0,0 +1,340 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+
+use databend_driver::{NumberValue, Value};
+use databend_driver_core::schema::DecimalSize;
+
+use crate::error::{Error, Result};
+
+/// A databend column type.
+///
+/// The derive macro builds these from `#[airbend_col(dtype = "...")]` and rejects unknown
+/// types at compile time. Nullability of a column is tracked separately by [crate::Field::nullable];
+/// [ColumnType::Nullable] is only needed for nested types such as `ARRAY(NULLABLE(INT))`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ColumnType {
+    Boolean,
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    UInt8,
+    UInt16,
+    UInt32,
+    UInt64,
+    Float32,
+    Float64,
+    Decimal { precision: u8, scale: u8 },
+    String,
+    Binary,
+    Date,
+    Timestamp,
+    Variant,
+    Bitmap,
+    Geometry,
+    Nullable(Box<ColumnType>),
+    Array(Box<ColumnType>),
+    Map(Box<ColumnType>, Box<ColumnType>),
+    Tuple(Vec<ColumnType>),
+}
+
+/// Renders the type as databend reports it, so it can be used directly in DDL.
+impl std::fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ColumnType::Boolean => write!(f, "BOOLEAN"),
+            ColumnType::Int8 => write!(f, "TINYINT"),
+            ColumnType::Int16 => write!(f, "SMALLINT"),
+            ColumnType::Int32 => write!(f, "INT"),
+            ColumnType::Int64 => write!(f, "BIGINT"),
+            ColumnType::UInt8 => write!(f, "TINYINT UNSIGNED"),
+            ColumnType::UInt16 => write!(f, "SMALLINT UNSIGNED"),
+            ColumnType::UInt32 => write!(f, "INT UNSIGNED"),
+            ColumnType::UInt64 => write!(f, "BIGINT UNSIGNED"),
+            ColumnType::Float32 => write!(f, "FLOAT"),
+            ColumnType::Float64 => write!(f, "DOUBLE"),
+            ColumnType::Decimal { precision, scale } => {
+                write!(f, "DECIMAL({}, {})", precision, scale)
+            }
+            ColumnType::String => write!(f, "VARCHAR"),
+            ColumnType::Binary => write!(f, "BINARY"),
+            ColumnType::Date => write!(f, "DATE"),
+            ColumnType::Timestamp => write!(f, "TIMESTAMP"),
+            ColumnType::Variant => write!(f, "VARIANT"),
+            ColumnType::Bitmap => write!(f, "BITMAP"),
+            ColumnType::Geometry => write!(f, "GEOMETRY"),
+            ColumnType::Nullable(inner) => write!(f, "NULLABLE({})", inner),
+            ColumnType::Array(inner) => write!(f, "ARRAY({})", inner),
+            ColumnType::Map(key, value) => write!(f, "MAP({}, {})", key, value),
+            ColumnType::Tuple(types) => {
+                let types: Vec<_> = types.iter().map(|t| t.to_string()).collect();
+                write!(f, "TUPLE({})", types.join(", "))
+            }
+        }
+    }
+}
+
+/// Marker types mirroring [ColumnType], used to check at compile time that a field's
+/// Rust type can be stored in its declared column type.
+pub mod kind {
+    use std::marker::PhantomData;
+
+    pub struct Boolean;
+    pub struct Int8;
+    pub struct Int16;
+    pub struct Int32;
+    pub struct Int64;
+    pub struct UInt8;
+    pub struct UInt16;
+    pub struct UInt32;
+    pub struct UInt64;
+    pub struct Float32;
+    pub struct Float64;
+    pub struct Decimal;
+    pub struct String;
+    pub struct Binary;
+    pub struct Date;
+    pub struct Timestamp;
+    pub struct Variant;
+    pub struct Bitmap;
+    pub struct Geometry;
+    pub struct Array<K>(PhantomData<K>);
+    pub struct Map<K, V>(PhantomData<(K, V)>);
+    /// `T` is a Rust tuple of kinds, one per element.
+    pub struct Tuple<T>(PhantomData<T>);
+}
+
+/// Implemented by Rust types that can be stored in a column of kind `K`.
+/// Nullability is not part of the kind, so `Option<T>` fits wherever `T` does.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` cannot be stored in a column of kind `{K}`",
+    label = "this field's type does not match its `dtype`"
+)]
+pub trait ColumnValue<K> {}
+
+/// Called by code generated by the derive macro. Fails to compile if `T` does not fit `K`.
+#[doc(hidden)]
+pub fn assert_column_value<T: ColumnValue<K> + ?Sized, K>() {}
+
+impl<K, T: ColumnValue<K>> ColumnValue<K> for Option<T> {}
+
+impl ColumnValue<kind::String> for String {}
+impl ColumnValue<kind::String> for &str {}
+impl ColumnValue<kind::String> for &String {}
+impl ColumnValue<kind::String> for Cow<'_, str> {}
+
+// Databend parses these from their text representation on insert.
+impl ColumnValue<kind::Variant> for String {}
+impl ColumnValue<kind::Variant> for &str {}
+impl ColumnValue<kind::Variant> for Cow<'_, str> {}
+impl ColumnValue<kind::Geometry> for String {}
+impl ColumnValue<kind::Geometry> for &str {}
+impl ColumnValue<kind::Geometry> for Cow<'_, str> {}
+impl ColumnValue<kind::Bitmap> for String {}
+impl ColumnValue<kind::Bitmap> for &str {}
+impl ColumnValue<kind::Bitmap> for Cow<'_, str> {}
+
+impl ColumnValue<kind::Boolean> for bool {}
+impl ColumnValue<kind::Int8> for i8 {}
+impl ColumnValue<kind::Int16> for i16 {}
+impl ColumnValue<kind::Int32> for i32 {}
+impl ColumnValue<kind::Int64> for i64 {}
+impl ColumnValue<kind::UInt8> for u8 {}
+impl ColumnValue<kind::UInt16> for u16 {}
+impl ColumnValue<kind::UInt32> for u32 {}
+impl ColumnValue<kind::UInt64> for u64 {}
+impl ColumnValue<kind::Float32> for f32 {}
+impl ColumnValue<kind::Float64> for f64 {}
+impl ColumnValue<kind::Decimal> for rust_decimal::Decimal {}
+impl ColumnValue<kind::Date> for jiff::civil::Date {}
+impl ColumnValue<kind::Timestamp> for jiff::Timestamp {}
+impl ColumnValue<kind::Timestamp> for jiff::civil::DateTime {}
+impl ColumnValue<kind::Timestamp> for jiff::Zoned {}
+impl ColumnValue<kind::Variant> for serde_json::Value {}
+
+impl<K, T: ColumnValue<K>> ColumnValue<kind::Array<K>> for Vec<T> {}
+impl<KK, VK, K: ColumnValue<KK>, V: ColumnValue<VK>> ColumnValue<kind::Map<KK, VK>>
+    for BTreeMap<K, V>
+{
+}
+impl<KK, VK, K: ColumnValue<KK>, V: ColumnValue<VK>, S> ColumnValue<kind::Map<KK, VK>>
+    for HashMap<K, V, S>
+{
+}
+
+/// The column type used for a field when `dtype` is left out of `#[airbend_col]`.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` has no default column type",
+    label = "add a column type with #[airbend_col(dtype = \"...\")]"
+)]
+pub trait DefaultColumnType {
+    fn column_type() -> ColumnType;
+
+    /// Whether the type can hold `None`. Used to mark the elements of nested types as `NULLABLE`.
+    fn is_nullable() -> bool {
+        false
+    }
+}
+
+/// Nullability is inferred separately, so `Option<T>` uses the type of `T`.
+impl<T: DefaultColumnType> DefaultColumnType for Option<T> {
+    fn column_type() -> ColumnType {
+        T::column_type()
+    }
+
+    fn is_nullable() -> bool {
+        true
+    }
+}
+
+/// The column type of an element inside an `ARRAY`, `MAP` or `TUPLE`.
+/// Used by code generated by the `AirbendTuple` derive.
+pub fn element_type(column_type: ColumnType, nullable: bool) -> ColumnType {
+    if nullable {
+        ColumnType::Nullable(Box::new(column_type))
+    } else {
+        column_type
+    }
+}
+
+fn nested_column_type<T: DefaultColumnType>() -> Box<ColumnType> {
+    Box::new(element_type(T::column_type(), T::is_nullable()))
+}
+
+impl<T: DefaultColumnType> DefaultColumnType for Vec<T> {
+    fn column_type() -> ColumnType {
+        ColumnType::Array(nested_column_type::<T>())
+    }
+}
+
+impl<K: DefaultColumnType, V: DefaultColumnType> DefaultColumnType for BTreeMap<K, V> {
+    fn column_type() -> ColumnType {
+        ColumnType::Map(nested_column_type::<K>(), nested_column_type::<V>())
+    }
+}
+
+impl<K: DefaultColumnType, V: DefaultColumnType, S> DefaultColumnType for HashMap<K, V, S> {
+    fn column_type() -> ColumnType {
+        ColumnType::Map(nested_column_type::<K>(), nested_column_type::<V>())
+    }
+}
+
+macro_rules! default_column_type {
+    ($($t:ty => $column_type:ident),*) => {
+        $(
+            impl DefaultColumnType for $t {
+                fn column_type() -> ColumnType {
+                    ColumnType::$column_type
+                }
+            }
+        )*
+    };
+}
+
+default_column_type!(
+    String => String,
+    &str => String,
+    Cow<'_, str> => String,
+    bool => Boolean,
+    i8 => Int8,
+    i16 => Int16,
+    i32 => Int32,
+    i64 => Int64,
+    u8 => UInt8,
+    u16 => UInt16,
+    u32 => UInt32,
+    u64 => UInt64,
+    f32 => Float32,
+    f64 => Float64,
+    jiff::civil::Date => Date,
+    jiff::Timestamp => Timestamp,
+    jiff::civil::DateTime => Timestamp,
+    jiff::Zoned => Timestamp,
+    serde_json::Value => Variant
+);
+
+/// Parse the text form of a value for a column of the databend type `data_type`.
+pub(crate) fn value_from_text(text: String, data_type: &str) -> Result<Value> {
+    let number = |number: NumberValue| Ok(Value::Number(number));
+    match data_type {
+        "BOOLEAN" => match text.to_ascii_lowercase().as_str() {
+            "true" | "1" => Ok(Value::Boolean(true)),
+            "false" | "0" => Ok(Value::Boolean(false)),
+            _ => Err(Error::Conversion(format!("Invalid BOOLEAN {}", text))),
+        },
+        "TINYINT" => number(NumberValue::Int8(text.parse()?)),
+        "SMALLINT" => number(NumberValue::Int16(text.parse()?)),
+        "INT" => number(NumberValue::Int32(text.parse()?)),
+        "BIGINT" => number(NumberValue::Int64(text.parse()?)),
+        "TINYINT UNSIGNED" => number(NumberValue::UInt8(text.parse()?)),
+        "SMALLINT UNSIGNED" => number(NumberValue::UInt16(text.parse()?)),
+        "INT UNSIGNED" => number(NumberValue::UInt32(text.parse()?)),
+        "BIGINT UNSIGNED" => number(NumberValue::UInt64(text.parse()?)),
+        "FLOAT" => number(NumberValue::Float32(text.parse()?)),
+        "DOUBLE" => number(NumberValue::Float64(text.parse()?)),
+        "VARCHAR" => Ok(Value::String(text)),
+        "BINARY" => Ok(Value::Binary(text.into_bytes())),
+        "VARIANT" => Ok(Value::Variant(text)),
+        "BITMAP" => Ok(Value::Bitmap(text)),
+        "GEOMETRY" => Ok(Value::Geometry(text)),
+        "DATE" => {
+            let date: jiff::civil::Date = text.parse()?;
+            Ok(Value::Date(
+                (date - jiff::civil::date(1970, 1, 1)).get_days(),
+            ))
+        }
+        "TIMESTAMP" => {
+            // Either an instant with an offset, or a datetime in UTC
+            let timestamp = match text.parse::<jiff::Timestamp>() {
+                Ok(timestamp) => timestamp,
+                Err(_) => text
+                    .parse::<jiff::civil::DateTime>()?
+                    .to_zoned(jiff::tz::TimeZone::UTC)?
+                    .timestamp(),
+            };
+            Ok(Value::Timestamp(timestamp.as_microsecond()))
+        }
+        _ => match data_type
+            .strip_prefix("DECIMAL(")
+            .and_then(|arguments| arguments.strip_suffix(')'))
+        {
+            Some(arguments) => decimal(&text, arguments),
+            None => Err(Error::Conversion(format!(
+                "Unsupported column type {}",
+                data_type
+            ))),
+        },
+    }
+}
+
+/// Parse decimal text such as `-1.25` for a `DECIMAL(precision, scale)` column.
+fn decimal(text: &str, arguments: &str) -> Result<Value> {
+    let Some((precision, scale)) = arguments.split_once(',') else {
+        return Err(Error::Conversion(format!(
+            "Invalid DECIMAL type arguments {}",
+            arguments
+        )));
+    };
+    let size = DecimalSize {
+        precision: precision.trim().parse()?,
+        scale: scale.trim().parse()?,
+    };
+
+    let (whole, fraction) = text.split_once('.').unwrap_or((text, ""));
+    if fraction.len() > size.scale as usize {
+        return Err(Error::Conversion(format!(
+            "{} has more than {} decimal places",
+            text, size.scale
+        )));
+    }
+    let digits = format!(
+        "{}{:0<width$}",
+        whole,
+        fraction,
+        width = size.scale as usize
+    );
+    Ok(Value::Number(NumberValue::Decimal128(
+        digits.parse()?,
+        size,
+    )))
+}
@@ -0,0 +1,32 @@
+//! Two types registered for the same table, which only this test binary links.
+
+use airbend_table::mock::MockConnection;
+use airbend_table::{create_all, migrate_all, AirbendTable, Error, MigrateOptions};
+
+#[derive(AirbendTable)]
+#[airbend_table(table_name = "reading")]
+struct Reading {
+    site_code: String,
+}
+
+#[derive(AirbendTable)]
+#[airbend_table(table_name = "reading")]
+struct OtherReading {
+    value: f64,
+}
+
+#[tokio::test]
+async fn duplicate_table_names_are_rejected() {
+    let conn = MockConnection::new();
+    let error = create_all(&conn).await.unwrap_err();
+    assert!(
+        matches!(&error, Error::Generation(message) if message.contains("table reading")),
+        "{error}"
+    );
+    assert!(conn.executed().is_empty());
+
+    let error = migrate_all(&conn, MigrateOptions::default())
+        .await
+        .unwrap_err();
+    assert!(matches!(error, Error::Generation(_)), "{error}");
+}
@@ -0,0 +1,53 @@
+//! Only the tables in this file are registered in this test binary.
+
+use airbend_table::mock::MockConnection;
+use airbend_table::registry::{ddl, tables};
+use airbend_table::{create_all, migrate_all, AirbendTable, Databend, MigrateOptions, Namespace};
+
+#[derive(AirbendTable)]
+#[airbend_table(table_name = "reading")]
+struct Reading {
+    site_code: String,
+    value: f64,
+}
+
+#[derive(AirbendTable)]
+#[airbend_table(table_name = "site", database = "meta")]
+struct Site {
+    site_code: String,
+}
+
+#[test]
+fn lists_tables_by_name() {
+    let names: Vec<_> = tables().iter().map(|table| table.name()).collect();
+    assert_eq!(names, ["reading", "site"]);
+}
+
+#[test]
+fn ddl_creates_every_table() {
+    assert_eq!(
+        ddl(&Databend, &Namespace::new()),
+        "CREATE DATABASE IF NOT EXISTS `meta`;\n\
+         CREATE TABLE IF NOT EXISTS `reading` (`site_code` VARCHAR NOT NULL, `value` DOUBLE NOT NULL);\n\
+         CREATE TABLE IF NOT EXISTS `meta`.`site` (`site_code` VARCHAR NOT NULL);\n"
+    );
+    assert_eq!(
+        ddl(&Databend, &Namespace::new().database("dev").table_prefix("dev_")),
+        "CREATE DATABASE IF NOT EXISTS `dev`;\n\
+         CREATE TABLE IF NOT EXISTS `dev`.`dev_reading` (`site_code` VARCHAR NOT NULL, `value` DOUBLE NOT NULL);\n\
+         CREATE TABLE IF NOT EXISTS `dev`.`dev_site` (`site_code` VARCHAR NOT NULL);\n"
+    );
+}
+
+#[tokio::test]
+async fn create_all_creates_every_table() {
+    let conn = MockConnection::new();
+    create_all(&conn).await.unwrap();
+    assert_eq!(conn.databases(), ["meta"]);
+    assert_eq!(conn.tables(), ["meta.site", "reading"]);
+
+    // Every table now matches its definition
+    let migrations = migrate_all(&conn, MigrateOptions::default()).await.unwrap();
+    assert_eq!(migrations.len(), 2);
+    assert!(migrations.iter().all(|migration| migration.is_empty()));
+}
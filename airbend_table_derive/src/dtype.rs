@@ -0,0 +1,330 @@
+//! Parses `dtype` strings at compile time so that typos are reported by the compiler.
+use proc_macro2::TokenStream;
+use quote::quote;
+
+/// Mirrors `airbend_table::ColumnType`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DType {
+    Boolean,
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    UInt8,
+    UInt16,
+    UInt32,
+    UInt64,
+    Float32,
+    Float64,
+    Decimal { precision: u8, scale: u8 },
+    String,
+    Binary,
+    Date,
+    Timestamp,
+    Variant,
+    Bitmap,
+    Geometry,
+    Nullable(Box<DType>),
+    Array(Box<DType>),
+    Map(Box<DType>, Box<DType>),
+    Tuple(Vec<DType>),
+}
+
+impl DType {
+    pub fn parse(s: &str) -> Result<DType, String> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            position: 0,
+        };
+        let dtype = parser.parse_type()?;
+        match parser.next() {
+            None => Ok(dtype),
+            Some(token) => Err(format!("unexpected `{}` after type", token)),
+        }
+    }
+
+    /// Expression building the matching `airbend_table::ColumnType`.
+    pub fn column_type(&self) -> TokenStream {
+        let boxed = |inner: &DType| {
+            let inner = inner.column_type();
+            quote!(::std::boxed::Box::new(#inner))
+        };
+        match self {
+            DType::Boolean => quote!(airbend_table::ColumnType::Boolean),
+            DType::Int8 => quote!(airbend_table::ColumnType::Int8),
+            DType::Int16 => quote!(airbend_table::ColumnType::Int16),
+            DType::Int32 => quote!(airbend_table::ColumnType::Int32),
+            DType::Int64 => quote!(airbend_table::ColumnType::Int64),
+            DType::UInt8 => quote!(airbend_table::ColumnType::UInt8),
+            DType::UInt16 => quote!(airbend_table::ColumnType::UInt16),
+            DType::UInt32 => quote!(airbend_table::ColumnType::UInt32),
+            DType::UInt64 => quote!(airbend_table::ColumnType::UInt64),
+            DType::Float32 => quote!(airbend_table::ColumnType::Float32),
+            DType::Float64 => quote!(airbend_table::ColumnType::Float64),
+            DType::Decimal { precision, scale } => quote!(airbend_table::ColumnType::Decimal {
+                precision: #precision,
+                scale: #scale
+            }),
+            DType::String => quote!(airbend_table::ColumnType::String),
+            DType::Binary => quote!(airbend_table::ColumnType::Binary),
+            DType::Date => quote!(airbend_table::ColumnType::Date),
+            DType::Timestamp => quote!(airbend_table::ColumnType::Timestamp),
+            DType::Variant => quote!(airbend_table::ColumnType::Variant),
+            DType::Bitmap => quote!(airbend_table::ColumnType::Bitmap),
+            DType::Geometry => quote!(airbend_table::ColumnType::Geometry),
+            DType::Nullable(inner) => {
+                let inner = boxed(inner);
+                quote!(airbend_table::ColumnType::Nullable(#inner))
+            }
+            DType::Array(inner) => {
+                let inner = boxed(inner);
+                quote!(airbend_table::ColumnType::Array(#inner))
+            }
+            DType::Map(key, value) => {
+                let key = boxed(key);
+                let value = boxed(value);
+                quote!(airbend_table::ColumnType::Map(#key, #value))
+            }
+            DType::Tuple(types) => {
+                let types = types.iter().map(DType::column_type);
+                quote!(airbend_table::ColumnType::Tuple(::std::vec![#(#types),*]))
+            }
+        }
+    }
+
+    /// The marker type from `airbend_table::types::kind` used to check the field's Rust type.
+    /// Nullability is ignored here; it is checked separately.
+    pub fn kind(&self) -> TokenStream {
+        match self {
+            DType::Boolean => quote!(airbend_table::types::kind::Boolean),
+            DType::Int8 => quote!(airbend_table::types::kind::Int8),
+            DType::Int16 => quote!(airbend_table::types::kind::Int16),
+            DType::Int32 => quote!(airbend_table::types::kind::Int32),
+            DType::Int64 => quote!(airbend_table::types::kind::Int64),
+            DType::UInt8 => quote!(airbend_table::types::kind::UInt8),
+            DType::UInt16 => quote!(airbend_table::types::kind::UInt16),
+            DType::UInt32 => quote!(airbend_table::types::kind::UInt32),
+            DType::UInt64 => quote!(airbend_table::types::kind::UInt64),
+            DType::Float32 => quote!(airbend_table::types::kind::Float32),
+            DType::Float64 => quote!(airbend_table::types::kind::Float64),
+            DType::Decimal { .. } => quote!(airbend_table::types::kind::Decimal),
+            DType::String => quote!(airbend_table::types::kind::String),
+            DType::Binary => quote!(airbend_table::types::kind::Binary),
+            DType::Date => quote!(airbend_table::types::kind::Date),
+            DType::Timestamp => quote!(airbend_table::types::kind::Timestamp),
+            DType::Variant => quote!(airbend_table::types::kind::Variant),
+            DType::Bitmap => quote!(airbend_table::types::kind::Bitmap),
+            DType::Geometry => quote!(airbend_table::types::kind::Geometry),
+            DType::Nullable(inner) => inner.kind(),
+            DType::Array(inner) => {
+                let inner = inner.kind();
+                quote!(airbend_table::types::kind::Array<#inner>)
+            }
+            DType::Map(key, value) => {
+                let key = key.kind();
+                let value = value.kind();
+                quote!(airbend_table::types::kind::Map<#key, #value>)
+            }
+            DType::Tuple(types) => {
+                let types = types.iter().map(DType::kind);
+                quote!(airbend_table::types::kind::Tuple<(#(#types,)*)>)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Number(u8),
+    Open,
+    Close,
+    Comma,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "{}", word),
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+            Token::Comma => write!(f, ","),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            '(' => {
+                tokens.push(Token::Open);
+                chars.next();
+            }
+            ')' => {
+                tokens.push(Token::Close);
+                chars.next();
+            }
+            ',' => {
+                tokens.push(Token::Comma);
+                chars.next();
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            c if c.is_ascii_digit() => {
+                let mut number = String::new();
+                while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                    number.push(c);
+                    chars.next();
+                }
+                let number = number
+                    .parse()
+                    .map_err(|_| format!("`{}` is too large", number))?;
+                tokens.push(Token::Number(number));
+            }
+            c if c.is_ascii_alphabetic() => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek().filter(|c| c.is_ascii_alphanumeric()) {
+                    word.push(c.to_ascii_uppercase());
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+            c => return Err(format!("unexpected character `{}`", c)),
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(format!("expected `{}`, found `{}`", expected, token)),
+            None => Err(format!("expected `{}`", expected)),
+        }
+    }
+
+    fn number(&mut self) -> Result<u8, String> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(n),
+            Some(token) => Err(format!("expected a number, found `{}`", token)),
+            None => Err("expected a number".to_string()),
+        }
+    }
+
+    /// Parses a parenthesised, comma separated list of types.
+    fn type_arguments(&mut self) -> Result<Vec<DType>, String> {
+        self.expect(Token::Open)?;
+        let mut types = vec![self.parse_type()?];
+        while self.peek() == Some(&Token::Comma) {
+            self.next();
+            types.push(self.parse_type()?);
+        }
+        self.expect(Token::Close)?;
+        Ok(types)
+    }
+
+    fn single_argument(&mut self, name: &str) -> Result<Box<DType>, String> {
+        let mut types = self.type_arguments()?;
+        if types.len() != 1 {
+            return Err(format!("{} takes exactly one type", name));
+        }
+        Ok(Box::new(types.remove(0)))
+    }
+
+    fn parse_type(&mut self) -> Result<DType, String> {
+        let name = match self.next() {
+            Some(Token::Word(word)) => word,
+            Some(token) => return Err(format!("expected a type, found `{}`", token)),
+            None => return Err("expected a type".to_string()),
+        };
+
+        // Some types are spelled with two words, e.g. INT UNSIGNED.
+        let unsigned = matches!(self.peek(), Some(Token::Word(w)) if w == "UNSIGNED");
+        let precision = matches!(self.peek(), Some(Token::Word(w)) if w == "PRECISION");
+        if unsigned || precision {
+            self.next();
+        }
+
+        let dtype = match (name.as_str(), unsigned, precision) {
+            ("BOOLEAN" | "BOOL", false, false) => DType::Boolean,
+            ("TINYINT" | "INT8", false, false) => DType::Int8,
+            ("SMALLINT" | "INT16", false, false) => DType::Int16,
+            ("INT" | "INTEGER" | "INT32", false, false) => DType::Int32,
+            ("BIGINT" | "INT64", false, false) => DType::Int64,
+            ("TINYINT", true, false) | ("UINT8", false, false) => DType::UInt8,
+            ("SMALLINT", true, false) | ("UINT16", false, false) => DType::UInt16,
+            ("INT" | "INTEGER", true, false) | ("UINT32", false, false) => DType::UInt32,
+            ("BIGINT", true, false) | ("UINT64", false, false) => DType::UInt64,
+            ("FLOAT" | "FLOAT32" | "REAL", false, false) => DType::Float32,
+            ("DOUBLE", false, _) | ("FLOAT64", false, false) => DType::Float64,
+            ("DECIMAL", false, false) => {
+                self.expect(Token::Open)?;
+                let precision = self.number()?;
+                let scale = if self.peek() == Some(&Token::Comma) {
+                    self.next();
+                    self.number()?
+                } else {
+                    0
+                };
+                self.expect(Token::Close)?;
+                if !(1..=76).contains(&precision) || scale > precision {
+                    return Err(format!(
+                        "invalid DECIMAL({}, {}): precision must be 1 to 76 and scale no larger than precision",
+                        precision, scale
+                    ));
+                }
+                DType::Decimal { precision, scale }
+            }
+            ("VARCHAR" | "STRING" | "TEXT", false, false) => DType::String,
+            ("BINARY" | "VARBINARY", false, false) => DType::Binary,
+            ("DATE", false, false) => DType::Date,
+            ("TIMESTAMP" | "DATETIME", false, false) => DType::Timestamp,
+            ("VARIANT" | "JSON", false, false) => DType::Variant,
+            ("BITMAP", false, false) => DType::Bitmap,
+            ("GEOMETRY", false, false) => DType::Geometry,
+            ("NULLABLE", false, false) => DType::Nullable(self.single_argument("NULLABLE")?),
+            ("ARRAY", false, false) => DType::Array(self.single_argument("ARRAY")?),
+            ("MAP", false, false) => {
+                let mut types = self.type_arguments()?;
+                if types.len() != 2 {
+                    return Err("MAP takes a key type and a value type".to_string());
+                }
+                let value = types.pop().unwrap();
+                let key = types.pop().unwrap();
+                DType::Map(Box::new(key), Box::new(value))
+            }
+            ("TUPLE", false, false) => DType::Tuple(self.type_arguments()?),
+            _ => {
+                let suffix = if unsigned {
+                    " UNSIGNED"
+                } else if precision {
+                    " PRECISION"
+                } else {
+                    ""
+                };
+                return Err(format!("unknown databend type `{}{}`", name, suffix));
+            }
+        };
+        Ok(dtype)
+    }
+}
@@ -1,107 +1,894 @@
-use quote::quote;
-use syn::{parse_macro_input, Data, DeriveInput, LitStr};
+mod case;
+mod dtype;
+
+use case::RenameRule;
+use dtype::DType;
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote, quote_spanned};
+use syn::{
+    ext::IdentExt, meta::ParseNestedMeta, parse_macro_input, punctuated::Punctuated,
+    spanned::Spanned, Attribute, Data, DeriveInput, Expr, ExprLit, Fields, Generics, Ident, Lit,
+    LitBool, LitStr, Meta, MetaNameValue, Token, Type,
+};
 
 #[proc_macro_derive(AirbendTable, attributes(airbend_table, airbend_col))]
 pub fn derive_airbend_table(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Maps a struct to a group of columns that can be embedded in `AirbendTable` structs with
+/// `#[airbend_col(flatten)]`. Fields take the same `#[airbend_col]` properties as a table's,
+/// and `#[airbend_table(rename_all = "...")]` is the only table property supported.
+#[proc_macro_derive(AirbendColumns, attributes(airbend_table, airbend_col))]
+pub fn derive_airbend_columns(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_columns(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Maps a struct to a databend `TUPLE`, so it can be the type of a column of an
+/// `AirbendTable` struct. Every field is an element of the tuple, in declaration order, and
+/// takes the `dtype`, `nullable`, `variant` and `skip` properties of `#[airbend_col]`.
+#[proc_macro_derive(AirbendTuple, attributes(airbend_col))]
+pub fn derive_airbend_tuple(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_tuple(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
 
+fn expand_tuple(input: DeriveInput) -> syn::Result<TokenStream> {
     let name = &input.ident;
+    let generics = &input.generics;
+    let (impl_generics, ty_generics, _) = generics.split_for_impl();
+    let fields = parse_fields(&input, "AirbendTuple", None)?;
+
+    let mut element_types = vec![];
+    let mut type_checks = vec![];
+    let mut to_values = vec![];
+    let mut from_values = vec![];
+    let mut kinds = vec![];
+    let mut kind_bounds = vec![];
+    let mut schema_bounds = vec![];
+    let mut write_bounds = vec![];
+    let mut read_bounds = vec![];
+    for field in fields {
+        let (ident, ty) = (field.ident, field.ty);
+        let column = match field.kind {
+            FieldKind::Column(column) => column,
+            FieldKind::Skip => {
+                from_values.push(quote!(#ident: ::std::default::Default::default()));
+                read_bounds.push(quote!(#ty: ::std::default::Default));
+                continue;
+            }
+            FieldKind::Flatten => {
+                return Err(syn::Error::new(
+                    ty.span(),
+                    "AirbendTuple does not support `flatten`. Derive AirbendTuple for the field's type instead",
+                ))
+            }
+        };
+        let index = element_types.len();
+        let column_type = &column.column_type;
+        let nullable = column.nullable;
+        element_types.push(quote!(
+            airbend_table::__private::element_type(#column_type, #nullable)
+        ));
+        let to_value = column.write_value(quote!(self.#ident));
+        to_values.push(quote!(#to_value?));
+        let from_value = column.read_value(quote!(elements), quote!(#index));
+        from_values.push(quote!(#ident: #from_value));
+        schema_bounds.extend(column.schema_bound(ty));
+        write_bounds.push(column.write_bound(ty));
+        read_bounds.push(column.read_bound(ty));
+
+        let kind = format_ident!("K{}", index);
+        kind_bounds.push(quote!(#ty: airbend_table::types::ColumnValue<#kind>));
+        kinds.push(kind);
+        type_checks.push(column.type_check);
+    }
+
+    let params = generics.params.iter();
+    let kind_where = where_clause(generics, &kind_bounds, true);
+    let schema_where = where_clause(generics, &schema_bounds, false);
+    let write_where = where_clause(generics, &write_bounds, false);
+    let read_where = where_clause(generics, &read_bounds, false);
+    Ok(quote! {
+        impl #impl_generics airbend_table::types::DefaultColumnType for #name #ty_generics
+        #schema_where
+        {
+            fn column_type() -> airbend_table::ColumnType {
+                #(#type_checks)*
+                airbend_table::ColumnType::Tuple(::std::vec![#(#element_types),*])
+            }
+        }
+
+        // Lets the struct be used in columns with an explicit `TUPLE(...)` dtype
+        impl<#(#params,)* #(#kinds),*> airbend_table::types::ColumnValue<
+            airbend_table::types::kind::Tuple<(#(#kinds,)*)>
+        > for #name #ty_generics
+        #kind_where
+        {
+        }
+
+        impl #impl_generics airbend_table::ToValue for #name #ty_generics #write_where {
+            fn to_value(&self) -> airbend_table::Result<airbend_table::InsertValue> {
+                Ok(airbend_table::__private::tuple(::std::vec![#(#to_values),*]))
+            }
+        }
+
+        impl #impl_generics airbend_table::FromValue for #name #ty_generics #read_where {
+            fn from_value(value: &airbend_table::Value) -> airbend_table::Result<Self> {
+                let elements = airbend_table::__private::tuple_elements(value)?;
+                Ok(Self {
+                    #(#from_values),*
+                })
+            }
+        }
+    })
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let generics = &input.generics;
+    let (impl_generics, ty_generics, _) = generics.split_for_impl();
+    let table = parse_table_attrs(&input, "AirbendTable")?;
+    let table_name = table.name.ok_or_else(|| {
+        syn::Error::new(
+            input.ident.span(),
+            "missing table_name. Try adding #[airbend_table(table_name = \"my_table_name\")]",
+        )
+    })?;
+
+    let fields = parse_fields(&input, "AirbendTable", table.rename_all)?;
+
+    // Cluster keys must name columns, not fields. The columns of flattened fields are only
+    // known once their types are, so any unknown name may be one of those.
+    let has_flattened = fields
+        .iter()
+        .any(|field| matches!(field.kind, FieldKind::Flatten));
+    for column in &table.cluster_by {
+        let is_column = fields.iter().any(|field| match &field.kind {
+            FieldKind::Column(field) => field.name == column.value(),
+            _ => false,
+        });
+        if !is_column && !has_flattened {
+            return Err(syn::Error::new(
+                column.span(),
+                format!(
+                    "cluster_by column `{}` is not a column of this table",
+                    column.value()
+                ),
+            ));
+        }
+    }
+    let cluster_by = &table.cluster_by;
+    let transient = table.transient;
+    let database = option_tokens(table.database.as_ref().map(LitStr::value));
+    let table_comment = option_tokens(doc_comment(&input.attrs));
+    let (option_names, option_values): (Vec<_>, Vec<_>) = table
+        .options
+        .iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .unzip();
+
+    let RowCode {
+        type_checks,
+        schema,
+        to_row,
+        write_row,
+        from_row,
+        column_count,
+        write_bounds,
+        read_bounds,
+    } = row_code(fields);
+    let table_where = where_clause(generics, &write_bounds, false);
+    let read_where = where_clause(generics, &read_bounds, false);
+
+    // Generic tables are registered once their parameters are known, which the derive
+    // cannot do. Lifetimes are the exception: the table is the same whatever they are.
+    let is_registered =
+        generics.type_params().next().is_none() && generics.const_params().next().is_none();
+    let registration = if is_registered {
+        let lifetimes = generics.lifetimes().map(|_| quote!('static));
+        quote! {
+            airbend_table::__private::inventory::submit! {
+                airbend_table::TableDef::of::<#name<#(#lifetimes),*>>()
+            }
+        }
+    } else {
+        quote!()
+    };
+
+    // Build the output, possibly using quasi-quotation
+    Ok(quote! {
+
+        impl #impl_generics airbend_table::Table for #name #ty_generics #table_where {
+             fn name() -> &'static str {
+                 #table_name
+             }
+
+             fn schema() -> Vec<airbend_table::Field> {
+                 #(#type_checks)*
+                 let mut fields = ::std::vec::Vec::with_capacity(#column_count);
+                 #(#schema)*
+                 fields
+             }
+             fn to_row(&self) -> airbend_table::Result<Vec<airbend_table::InsertValue>> {
+                 let mut row = ::std::vec::Vec::with_capacity(#column_count);
+                 #(#to_row)*
+                 Ok(row)
+             }
+
+             fn write_row(
+                 &self,
+                 row: &mut airbend_table::RowWriter<'_>,
+             ) -> airbend_table::Result<()> {
+                 #(#write_row)*
+                 Ok(())
+             }
+
+             fn database() -> ::std::option::Option<&'static str> {
+                 #database
+             }
+
+             fn options() -> airbend_table::TableOptions {
+                 airbend_table::TableOptions {
+                     cluster_by: vec![#(#cluster_by),*],
+                     comment: #table_comment,
+                     transient: #transient,
+                     options: vec![#((#option_names, #option_values)),*],
+                 }
+             }
+
+        }
+
+        #registration
+
+        impl #impl_generics airbend_table::FromRow for #name #ty_generics #read_where {
+             fn from_row(row: &[airbend_table::Value]) -> airbend_table::Result<Self> {
+                 Ok(Self {
+                     #(#from_row),*
+                 })
+             }
+        }
+
+    })
+}
+
+fn expand_columns(input: DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let generics = &input.generics;
+    let (impl_generics, ty_generics, _) = generics.split_for_impl();
+    let attrs = parse_table_attrs(&input, "AirbendColumns")?;
+    let fields = parse_fields(&input, "AirbendColumns", attrs.rename_all)?;
+
+    let RowCode {
+        type_checks,
+        schema,
+        to_row,
+        write_row,
+        from_row,
+        column_count,
+        write_bounds,
+        read_bounds,
+    } = row_code(fields);
+    let write_where = where_clause(generics, &write_bounds, false);
+    let read_where = where_clause(generics, &read_bounds, false);
+
+    Ok(quote! {
+        impl #impl_generics airbend_table::Columns for #name #ty_generics #write_where {
+            const COLUMN_COUNT: usize = #column_count;
+
+            fn columns() -> ::std::vec::Vec<airbend_table::Field> {
+                #(#type_checks)*
+                let mut fields = ::std::vec::Vec::with_capacity(#column_count);
+                #(#schema)*
+                fields
+            }
+
+            fn to_values(
+                &self,
+            ) -> airbend_table::Result<::std::vec::Vec<airbend_table::InsertValue>> {
+                let mut row = ::std::vec::Vec::with_capacity(#column_count);
+                #(#to_row)*
+                Ok(row)
+            }
+
+            fn write_values(
+                &self,
+                row: &mut airbend_table::RowWriter<'_>,
+            ) -> airbend_table::Result<()> {
+                #(#write_row)*
+                Ok(())
+            }
+        }
+
+        impl #impl_generics airbend_table::FromRow for #name #ty_generics #read_where {
+            fn from_row(row: &[airbend_table::Value]) -> airbend_table::Result<Self> {
+                Ok(Self {
+                    #(#from_row),*
+                })
+            }
+        }
+    })
+}
+
+/// The code shared by the `AirbendTable` and `AirbendColumns` derives, which both map the
+/// fields of a struct to a run of columns.
+struct RowCode {
+    /// Statements that fail to compile if a field's type does not fit its `dtype`.
+    type_checks: Vec<TokenStream>,
+    /// Statements adding the `airbend_table::Field` of every column to `fields`.
+    schema: Vec<TokenStream>,
+    /// Statements adding the value of every column to `row`, a `Vec` of values.
+    to_row: Vec<TokenStream>,
+    /// Statements writing the value of every column to `row`, an `airbend_table::RowWriter`.
+    write_row: Vec<TokenStream>,
+    /// A `field: value` initializer for every field, reading columns from `row`.
+    from_row: Vec<TokenStream>,
+    /// Expression counting the columns.
+    column_count: TokenStream,
+    /// Bounds a generic struct needs to describe and write its columns.
+    write_bounds: Vec<TokenStream>,
+    /// Bounds a generic struct needs to be read from a row.
+    read_bounds: Vec<TokenStream>,
+}
+
+fn row_code(fields: Vec<ParsedField>) -> RowCode {
+    let mut code = RowCode {
+        type_checks: vec![],
+        schema: vec![],
+        to_row: vec![],
+        write_row: vec![],
+        from_row: vec![],
+        column_count: TokenStream::new(),
+        write_bounds: vec![],
+        read_bounds: vec![],
+    };
+    // Columns before the current field: a number of plain columns, then those of flattened fields
+    let mut columns = 0;
+    let mut flattened = vec![];
+    for field in fields {
+        let (ident, ty) = (field.ident, field.ty);
+        let index = column_offset(columns, &flattened);
+        match field.kind {
+            FieldKind::Column(column) => {
+                let to_value = column.write_value(quote!(self.#ident));
+                let write_value = column.write_sql(quote!(row), quote!(self.#ident));
+                let from_value = column.read_value(quote!(row), index);
+                let field = &column.field;
+                code.schema.push(quote!(fields.push(#field);));
+                code.to_row.push(quote!(row.push(#to_value?);));
+                code.write_row.push(quote!(#write_value?;));
+                code.from_row.push(quote!(#ident: #from_value));
+                code.write_bounds.extend(column.schema_bound(ty));
+                code.write_bounds.push(column.write_bound(ty));
+                code.read_bounds.push(column.read_bound(ty));
+                code.type_checks.push(column.type_check);
+                columns += 1;
+            }
+            FieldKind::Flatten => {
+                code.schema.push(quote!(
+                    fields.extend(<#ty as airbend_table::Columns>::columns());
+                ));
+                code.to_row.push(quote!(
+                    row.extend(<#ty as airbend_table::Columns>::to_values(&self.#ident)?);
+                ));
+                code.write_row.push(quote!(
+                    <#ty as airbend_table::Columns>::write_values(&self.#ident, row)?;
+                ));
+                code.from_row.push(quote!(
+                    #ident: airbend_table::__private::flattened(row, #index)?
+                ));
+                code.write_bounds.push(quote!(#ty: airbend_table::Columns));
+                code.read_bounds.push(quote!(#ty: airbend_table::FromRow));
+                flattened.push(ty);
+            }
+            // Fields that are not columns are filled with their default value
+            FieldKind::Skip => {
+                code.from_row.push(quote!(
+                    #ident: ::std::default::Default::default()
+                ));
+                code.read_bounds.push(quote!(#ty: ::std::default::Default));
+            }
+        }
+    }
+    code.column_count = column_offset(columns, &flattened);
+    code
+}
+
+/// Expression adding `columns` to the column counts of the `flattened` types.
+fn column_offset(columns: usize, flattened: &[&Type]) -> TokenStream {
+    let counts = flattened
+        .iter()
+        .map(|ty| quote!(<#ty as airbend_table::Columns>::COLUMN_COUNT));
+    if flattened.is_empty() || columns > 0 {
+        quote!(#columns #(+ #counts)*)
+    } else {
+        quote!(#(#counts)+*)
+    }
+}
+
+/// The `where` clause of an impl for the struct: its own predicates, followed by `bounds` for
+/// generic structs. Non-generic structs leave the bounds out, so fields with unsupported
+/// types are reported at the field rather than at the derive, unless `always` is set.
+fn where_clause(generics: &Generics, bounds: &[TokenStream], always: bool) -> TokenStream {
+    let predicates = generics
+        .where_clause
+        .iter()
+        .flat_map(|clause| clause.predicates.iter());
+    let bounds = (always || !generics.params.is_empty()).then_some(bounds);
+    let bounds = bounds.into_iter().flatten();
+    quote!(where #(#predicates,)* #(#bounds,)*)
+}
+
+/// Parse the fields of a struct with named fields, reporting problems with every field at
+/// once rather than one per compile. Column names follow `rename_all` unless a field has a
+/// `name`.
+fn parse_fields<'a>(
+    input: &'a DeriveInput,
+    derive: &str,
+    rename_all: Option<RenameRule>,
+) -> syn::Result<Vec<ParsedField<'a>>> {
+    let struct_data = match &input.data {
+        Data::Struct(struct_data) => struct_data,
+        Data::Enum(data) => {
+            return Err(syn::Error::new(
+                data.enum_token.span(),
+                format!("{} can only be derived for structs", derive),
+            ))
+        }
+        Data::Union(data) => {
+            return Err(syn::Error::new(
+                data.union_token.span(),
+                format!("{} can only be derived for structs", derive),
+            ))
+        }
+    };
+
+    let named_fields = match &struct_data.fields {
+        Fields::Named(fields) => fields,
+        Fields::Unnamed(fields) => {
+            return Err(syn::Error::new(
+                fields.span(),
+                format!(
+                    "{} does not support tuple structs. Use a struct with named fields",
+                    derive
+                ),
+            ))
+        }
+        Fields::Unit => {
+            return Err(syn::Error::new(
+                input.ident.span(),
+                format!("{} does not support unit structs. Add a field", derive),
+            ))
+        }
+    };
+
+    let mut fields = vec![];
+    let mut errors: Option<syn::Error> = None;
+    for field in &named_fields.named {
+        match parse_field(field, rename_all) {
+            Ok(field) => fields.push(field),
+            Err(e) => match &mut errors {
+                Some(errors) => errors.combine(e),
+                None => errors = Some(e),
+            },
+        }
+    }
+    match errors {
+        Some(errors) => Err(errors),
+        None => Ok(fields),
+    }
+}
+
+/// The properties of `#[airbend_table(...)]`.
+struct TableAttrs {
+    name: Option<LitStr>,
+    database: Option<LitStr>,
+    cluster_by: Vec<LitStr>,
+    transient: bool,
+    options: Vec<(Ident, LitStr)>,
+    rename_all: Option<RenameRule>,
+}
+
+/// Reads `#[airbend_table(...)]`. Only `rename_all` applies to derives other than `AirbendTable`.
+fn parse_table_attrs(input: &DeriveInput, derive: &str) -> syn::Result<TableAttrs> {
+    let is_table = derive == "AirbendTable";
     let mut table_name: Option<LitStr> = None;
+    let mut database: Option<LitStr> = None;
+    let mut cluster_by: Option<Vec<LitStr>> = None;
+    let mut transient = false;
+    let mut options: Vec<(Ident, LitStr)> = vec![];
+    let mut rename_all: Option<LitStr> = None;
     for attr in &input.attrs {
         if attr.path().is_ident("airbend_table") {
             attr.parse_nested_meta(|meta| {
-                if meta.path.is_ident("table_name") {
-                    table_name = Some(meta.value().unwrap().parse().unwrap());
+                if meta.path.is_ident("rename_all") {
+                    set_once(&mut rename_all, &meta, meta.value()?.parse()?)
+                } else if !is_table {
+                    Err(meta.error(format!(
+                        "unsupported property. {} only supports `rename_all`",
+                        derive
+                    )))
+                } else if meta.path.is_ident("table_name") {
+                    set_once(&mut table_name, &meta, meta.value()?.parse()?)
+                } else if meta.path.is_ident("database") {
+                    set_once(&mut database, &meta, meta.value()?.parse()?)
+                } else if meta.path.is_ident("cluster_by") {
+                    let value = meta.value()?;
+                    let content;
+                    syn::bracketed!(content in value);
+                    let columns = Punctuated::<LitStr, Token![,]>::parse_terminated(&content)?;
+                    set_once(&mut cluster_by, &meta, columns.into_iter().collect())
+                } else if meta.path.is_ident("transient") {
+                    transient = true;
                     Ok(())
+                } else if meta.path.is_ident("options") {
+                    meta.parse_nested_meta(|option| {
+                        let name = option
+                            .path
+                            .get_ident()
+                            .ok_or_else(|| option.error("expected an option name"))?;
+                        if options.iter().any(|(existing, _)| existing == name) {
+                            return Err(option.error(format!("duplicate `{}` option", name)));
+                        }
+                        options.push((name.clone(), option.value()?.parse()?));
+                        Ok(())
+                    })
                 } else {
-                    Err(meta.error("unsupported table property"))
+                    Err(meta.error(
+                        "unsupported table property. Expected one of `table_name`, `database`, `cluster_by`, `transient`, `options` or `rename_all`",
+                    ))
                 }
-            })
-            .unwrap();
+            })?;
+        }
+    }
+
+    let rename_all = rename_all
+        .map(|rule| RenameRule::parse(&rule.value()).map_err(|e| syn::Error::new(rule.span(), e)))
+        .transpose()?;
+    Ok(TableAttrs {
+        name: table_name,
+        database,
+        cluster_by: cluster_by.unwrap_or_default(),
+        transient,
+        options,
+        rename_all,
+    })
+}
+
+/// A field of the struct and how it is stored.
+struct ParsedField<'a> {
+    ident: &'a Ident,
+    ty: &'a Type,
+    kind: FieldKind,
+}
+
+enum FieldKind {
+    Column(Column),
+    /// `#[airbend_col(flatten)]`: the columns of a type deriving `AirbendColumns`.
+    Flatten,
+    /// `#[airbend_col(skip)]`: not stored, and filled with its default value when read.
+    Skip,
+}
+
+/// The generated pieces for a struct field that maps to a column.
+struct Column {
+    name: String,
+    /// Expression building the `airbend_table::Field`.
+    field: TokenStream,
+    /// Expression building the column's `airbend_table::ColumnType`.
+    column_type: TokenStream,
+    /// The kind of the column's `dtype`, if it has one.
+    kind: Option<TokenStream>,
+    nullable: bool,
+    /// Stored as JSON in a `VARIANT` column through serde.
+    variant: bool,
+    /// Statement that fails to compile if the field's type does not fit its `dtype`.
+    type_check: TokenStream,
+}
+
+impl Column {
+    /// Expression converting `value`, the field, into an
+    /// `airbend_table::Result<airbend_table::InsertValue>` without moving it.
+    fn write_value(&self, value: TokenStream) -> TokenStream {
+        if self.variant {
+            let nullable = self.nullable;
+            quote!(airbend_table::__private::to_variant(&#value, #nullable))
+        } else {
+            quote!(airbend_table::ToValue::to_value(&#value))
         }
     }
 
-    if table_name.is_none() {
-        panic!("You must provide table_name. Try adding #[airbend_table(table_name = \"my_table_name\")]");
+    /// Expression writing `value`, the field, to `writer`, an `airbend_table::RowWriter`,
+    /// giving an `airbend_table::Result<()>`.
+    fn write_sql(&self, writer: TokenStream, value: TokenStream) -> TokenStream {
+        if self.variant {
+            let value = self.write_value(value);
+            quote!(#writer.value(&#value?))
+        } else {
+            quote!(#writer.value(&#value))
+        }
     }
 
-    let Data::Struct(struct_data) = input.data else {
-        unimplemented!("AirbendTable can only be derived for structs");
-    };
+    /// Expression reading the field from position `index` of `values`, a slice of values.
+    fn read_value(&self, values: TokenStream, index: TokenStream) -> TokenStream {
+        let name = &self.name;
+        if self.variant {
+            quote!(airbend_table::__private::from_variant(#values, #index, #name)?)
+        } else {
+            quote!(airbend_table::__private::from_column(#values, #index, #name)?)
+        }
+    }
 
-    let fields = struct_data.fields.iter().map(|field| {
-        let mut ignore_field: bool = true;
-        let mut col_name: Option<LitStr> = None;
-        let mut col_dtype: Option<LitStr> = None;
-        let Some(ident) = &field.ident else {
-            unimplemented!("AirbendTable does not support tuple structs");
-        };
-        for inner_attr in &field.attrs {
-            if inner_attr.path().is_ident("airbend_col") {
-                ignore_field = false;
-                inner_attr
-                    .parse_nested_meta(|meta| {
-                        if meta.path.is_ident("name") {
-                            col_name = Some(meta.value().unwrap().parse().unwrap());
-                            Ok(())
-                        } else if meta.path.is_ident("dtype") {
-                            col_dtype = Some(meta.value().unwrap().parse().unwrap());
-                            Ok(())
-                        } else {
-                            Err(meta.error("unsupported table property"))
-                        }
-                    })
-                    .unwrap();
-            }
+    /// The bound a field of type `ty` needs for its column type, if any.
+    fn schema_bound(&self, ty: &Type) -> Option<TokenStream> {
+        match &self.kind {
+            Some(kind) => Some(quote!(#ty: airbend_table::types::ColumnValue<#kind>)),
+            None if self.variant => None,
+            None => Some(quote!(#ty: airbend_table::types::DefaultColumnType)),
         }
+    }
 
-        let resolved_col_name = if let Some(col_name_lit_str) = col_name {
-            col_name_lit_str.value()
+    /// The bound a field of type `ty` needs for [Column::write_value].
+    fn write_bound(&self, ty: &Type) -> TokenStream {
+        if self.variant {
+            quote!(#ty: airbend_table::__private::serde::Serialize)
         } else {
-            ident.to_string()
-        };
+            quote!(#ty: airbend_table::ToValue)
+        }
+    }
 
-        if ignore_field {
-            (None, None)
+    /// The bound a field of type `ty` needs for [Column::read_value].
+    fn read_bound(&self, ty: &Type) -> TokenStream {
+        if self.variant {
+            quote!(#ty: airbend_table::__private::serde::de::DeserializeOwned)
         } else {
-            (
-                Some(quote!(airbend_table::Field {
-                    name: #resolved_col_name,
-                    data_type: #col_dtype,
-                    nullable: true
-                })),
-                Some(quote!(
-                    self.#ident.into()
-                )),
-            )
+            quote!(#ty: airbend_table::FromValue)
         }
-    });
+    }
+}
 
-    let (included_field_types, to_rows): (Vec<_>, Vec<_>) =
-        fields.filter(|i| i.0.is_some()).unzip();
+/// Reads `#[airbend_col(...)]` from a field. Every field is a column unless it is marked
+/// `skip` or `flatten`.
+fn parse_field(field: &syn::Field, rename_all: Option<RenameRule>) -> syn::Result<ParsedField<'_>> {
+    let ident = field
+        .ident
+        .as_ref()
+        .expect("named fields always have an identifier");
+    let ty = &field.ty;
 
-    // Build the output, possibly using quasi-quotation
-    let expanded = quote! {
+    let mut col_name: Option<LitStr> = None;
+    let mut col_dtype: Option<LitStr> = None;
+    let mut col_nullable: Option<LitBool> = None;
+    let mut key: Option<Ident> = None;
+    let mut is_variant = false;
+    // `skip` or `flatten`, which take no other properties
+    let mut not_column: Option<(FieldKind, Ident)> = None;
+    let mut column_property: Option<Ident> = None;
+    for inner_attr in &field.attrs {
+        if inner_attr.path().is_ident("airbend_col") {
+            // A bare #[airbend_col] uses the defaults for every property
+            if matches!(inner_attr.meta, Meta::Path(_)) {
+                continue;
+            }
+            inner_attr.parse_nested_meta(|meta| {
+                let property = meta
+                    .path
+                    .get_ident()
+                    .cloned()
+                    .ok_or_else(|| meta.error("expected a column property"))?;
+                if property == "skip" || property == "flatten" {
+                    if let Some((_, existing)) = &not_column {
+                        return Err(meta.error(format!(
+                            "`{}` cannot be combined with `{}`",
+                            property, existing
+                        )));
+                    }
+                    let kind = if property == "skip" {
+                        FieldKind::Skip
+                    } else {
+                        FieldKind::Flatten
+                    };
+                    not_column = Some((kind, property));
+                    return Ok(());
+                }
+                column_property.get_or_insert(property.clone());
+                if property == "name" {
+                    set_once(&mut col_name, &meta, meta.value()?.parse()?)
+                } else if property == "dtype" {
+                    set_once(&mut col_dtype, &meta, meta.value()?.parse()?)
+                } else if property == "nullable" {
+                    set_once(&mut col_nullable, &meta, meta.value()?.parse()?)
+                } else if property == "key" {
+                    key = Some(property);
+                    Ok(())
+                } else if property == "variant" {
+                    is_variant = true;
+                    Ok(())
+                } else {
+                    Err(meta.error(
+                        "unsupported column property. Expected one of `name`, `dtype`, `nullable`, `key`, `variant`, `flatten` or `skip`",
+                    ))
+                }
+            })?;
+        }
+    }
 
-        impl airbend_table::Table for #name {
-             fn name() -> &'static str {
-                 #table_name
-             }
+    if let Some((kind, not_column)) = not_column {
+        if let Some(property) = column_property {
+            return Err(syn::Error::new(
+                property.span(),
+                format!(
+                    "`{}` fields are not columns and cannot have `{}`",
+                    not_column, property
+                ),
+            ));
+        }
+        return Ok(ParsedField { ident, ty, kind });
+    }
 
-             fn schema() -> Vec<airbend_table::Field> {
-                 vec![
-                     #(#included_field_types),*
-                 ]
-             }
-             fn to_row(self) -> Vec<airbend_table::InsertValue> {
-                 vec![
-                      #(#to_rows),*
-                 ]
-             }
+    let resolved_col_name = if let Some(col_name_lit_str) = col_name {
+        let name = col_name_lit_str.value();
+        if name.is_empty() {
+            return Err(syn::Error::new(
+                col_name_lit_str.span(),
+                "column name cannot be empty",
+            ));
+        }
+        name
+    } else {
+        let field_name = ident.unraw().to_string();
+        match rename_all {
+            Some(rule) => rule.apply(&field_name),
+            None => field_name,
+        }
+    };
 
+    let (column_type, kind, type_check, declared_nullable) = match col_dtype {
+        Some(col_dtype) if is_variant => {
+            return Err(syn::Error::new(
+                col_dtype.span(),
+                "`variant` columns are always VARIANT. Remove the `dtype`",
+            ))
         }
+        // Checked by the serde bounds of the conversion functions instead
+        None if is_variant => (
+            quote!(airbend_table::ColumnType::Variant),
+            None,
+            quote!(),
+            false,
+        ),
+        Some(col_dtype) => {
+            let dtype = DType::parse(&col_dtype.value())
+                .map_err(|e| syn::Error::new(col_dtype.span(), format!("invalid dtype: {}", e)))?;
+            // Top level nullability is recorded on the field rather than in the type
+            let (dtype, declared_nullable) = match dtype {
+                DType::Nullable(inner) => (*inner, true),
+                dtype => (dtype, false),
+            };
+            let kind = dtype.kind();
+            (
+                dtype.column_type(),
+                Some(kind.clone()),
+                quote_spanned!(ty.span()=>
+                    airbend_table::__private::assert_column_value::<#ty, #kind>();
+                ),
+                declared_nullable,
+            )
+        }
+        // Infer the column type from the field's Rust type
+        None => (
+            quote_spanned!(ty.span()=>
+                <#ty as airbend_table::types::DefaultColumnType>::column_type()
+            ),
+            None,
+            quote!(),
+            false,
+        ),
+    };
+
+    let comment = option_tokens(doc_comment(&field.attrs));
 
+    let nullable = match col_nullable {
+        Some(nullable) => nullable.value(),
+        None => declared_nullable || is_option(ty),
     };
+    // NULL never equals NULL, so upserts and unique indexes would never match a NULL key
+    if let (Some(key), true) = (&key, nullable) {
+        return Err(syn::Error::new(
+            key.span(),
+            "`key` columns cannot be nullable, as a NULL key never matches an existing row",
+        ));
+    }
+    let is_key = key.is_some();
+
+    Ok(ParsedField {
+        ident,
+        ty,
+        kind: FieldKind::Column(Column {
+            field: quote!(airbend_table::Field {
+                name: #resolved_col_name,
+                data_type: #column_type,
+                nullable: #nullable,
+                key: #is_key,
+                comment: #comment
+            }),
+            name: resolved_col_name,
+            column_type,
+            kind,
+            nullable,
+            variant: is_variant,
+            type_check,
+        }),
+    })
+}
+
+/// Store an attribute value, rejecting a second occurrence of the same property.
+fn set_once<T>(slot: &mut Option<T>, meta: &ParseNestedMeta, value: T) -> syn::Result<()> {
+    if slot.is_some() {
+        let property = meta
+            .path
+            .get_ident()
+            .map(|ident| ident.to_string())
+            .unwrap_or_default();
+        return Err(meta.error(format!("duplicate `{}` property", property)));
+    }
+    *slot = Some(value);
+    Ok(())
+}
+
+/// The text of any `///` doc comments, with lines joined by newlines.
+fn doc_comment(attrs: &[Attribute]) -> Option<String> {
+    let lines: Vec<String> = attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(MetaNameValue {
+                value:
+                    Expr::Lit(ExprLit {
+                        lit: Lit::Str(line),
+                        ..
+                    }),
+                ..
+            }) => Some(line.value()),
+            _ => None,
+        })
+        .collect();
+    let comment = lines
+        .iter()
+        .map(|line| line.strip_prefix(' ').unwrap_or(line).trim_end())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let comment = comment.trim();
+    (!comment.is_empty()).then(|| comment.to_string())
+}
 
-    proc_macro::TokenStream::from(expanded)
+/// `Some("...")` or `None` as an expression.
+fn option_tokens(value: Option<String>) -> TokenStream {
+    match value {
+        Some(value) => quote!(::std::option::Option::Some(#value)),
+        None => quote!(::std::option::Option::None),
+    }
+}
+
+/// True if the type is written as `Option<...>`. Columns for these fields are nullable.
+fn is_option(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => {
+            path.qself.is_none()
+                && path
+                    .path
+                    .segments
+                    .last()
+                    .is_some_and(|segment| segment.ident == "Option")
+        }
+        _ => false,
+    }
 }
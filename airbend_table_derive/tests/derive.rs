@@ -0,0 +1,157 @@
+use airbend_table::mock::MockConnection;
+use airbend_table::{
+    create, insert, select, AirbendColumns, AirbendTable, Databend, Namespace, Table, TableDef,
+};
+
+fn column_names<T: Table>() -> Vec<&'static str> {
+    T::schema().iter().map(|field| field.name).collect()
+}
+
+#[derive(AirbendColumns, Debug, PartialEq)]
+struct Scrape {
+    scrape_time: jiff::Timestamp,
+    source_url: String,
+}
+
+#[derive(AirbendColumns, Debug, PartialEq)]
+struct Location {
+    latitude: f64,
+    longitude: f64,
+}
+
+#[derive(AirbendTable, Debug, PartialEq)]
+#[airbend_table(table_name = "site_reading")]
+struct SiteReading {
+    site_code: String,
+    #[airbend_col(flatten)]
+    scrape: Scrape,
+    value: f64,
+    #[airbend_col(flatten)]
+    location: Location,
+    species_code: Option<String>,
+}
+
+#[tokio::test]
+async fn flattened_columns_keep_their_place() {
+    assert_eq!(
+        column_names::<SiteReading>(),
+        [
+            "site_code",
+            "scrape_time",
+            "source_url",
+            "value",
+            "latitude",
+            "longitude",
+            "species_code"
+        ]
+    );
+
+    let conn = MockConnection::new();
+    create::<SiteReading>(&conn).await.unwrap();
+    let rows = [SiteReading {
+        site_code: "MY1".to_string(),
+        scrape: Scrape {
+            scrape_time: jiff::Timestamp::from_second(1_700_000_000).unwrap(),
+            source_url: "https://api.erg.ic.ac.uk".to_string(),
+        },
+        value: 1.5,
+        location: Location {
+            latitude: 51.52,
+            longitude: -0.15,
+        },
+        species_code: Some("NO2".to_string()),
+    }];
+    insert().values(&rows).execute(&conn).await.unwrap();
+    assert_eq!(select::<SiteReading>().fetch(&conn).await.unwrap(), rows);
+}
+
+#[derive(AirbendTable)]
+#[airbend_table(table_name = "site", rename_all = "camelCase")]
+struct Site {
+    site_code: String,
+    date_opened: Option<jiff::Timestamp>,
+    #[airbend_col(name = "URL")]
+    site_link: String,
+}
+
+#[test]
+fn rename_all_names_columns() {
+    assert_eq!(column_names::<Site>(), ["siteCode", "dateOpened", "URL"]);
+}
+
+#[derive(AirbendTable, Debug, PartialEq)]
+#[airbend_table(table_name = "skipping")]
+struct Skipping {
+    site_code: String,
+    #[airbend_col(skip)]
+    retries: u32,
+    #[airbend_col(skip)]
+    notes: Vec<String>,
+}
+
+#[tokio::test]
+async fn skipped_fields_are_not_stored() {
+    let namespace = Namespace::default();
+    assert_eq!(
+        TableDef::of::<Skipping>().create_sql(&Databend, &namespace),
+        "CREATE TABLE IF NOT EXISTS `skipping` (`site_code` VARCHAR NOT NULL);"
+    );
+    let rows = [Skipping {
+        site_code: "MY1".to_string(),
+        retries: 3,
+        notes: vec!["retried".to_string()],
+    }];
+    assert_eq!(
+        insert().values(&rows).sql(&Databend, &namespace).unwrap(),
+        "INSERT INTO `skipping` (`site_code`) VALUES ('MY1')"
+    );
+
+    let conn = MockConnection::new();
+    create::<Skipping>(&conn).await.unwrap();
+    insert().values(&rows).execute(&conn).await.unwrap();
+    assert_eq!(
+        select::<Skipping>().fetch(&conn).await.unwrap(),
+        [Skipping {
+            site_code: "MY1".to_string(),
+            retries: 0,
+            notes: vec![],
+        }]
+    );
+}
+
+#[derive(AirbendTable)]
+#[airbend_table(table_name = "borrowed")]
+struct Borrowed<'a> {
+    site_code: &'a str,
+    value: f64,
+}
+
+#[derive(AirbendTable, Debug, PartialEq)]
+#[airbend_table(table_name = "generic")]
+struct Generic<T> {
+    site_code: String,
+    value: T,
+}
+
+#[tokio::test]
+async fn generic_and_borrowed_tables_insert() {
+    let conn = MockConnection::new();
+
+    create::<Borrowed>(&conn).await.unwrap();
+    let site_code = String::from("MY1");
+    let rows = [Borrowed {
+        site_code: &site_code,
+        value: 1.5,
+    }];
+    let stats = insert().values(&rows).execute(&conn).await.unwrap();
+    assert_eq!(stats.rows, 1);
+    assert_eq!(conn.rows("borrowed").len(), 1);
+
+    create::<Generic<i64>>(&conn).await.unwrap();
+    let rows = [Generic {
+        site_code: "MY1".to_string(),
+        value: 7_i64,
+    }];
+    insert().values(&rows).execute(&conn).await.unwrap();
+    assert_eq!(select::<Generic<i64>>().fetch(&conn).await.unwrap(), rows);
+}
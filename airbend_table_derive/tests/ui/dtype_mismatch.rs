@@ -0,0 +1,10 @@
+use airbend_table::AirbendTable;
+
+#[derive(AirbendTable)]
+#[airbend_table(table_name = "reading")]
+struct Reading {
+    #[airbend_col(dtype = "INT")]
+    site: String,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use airbend_table::AirbendTable;
+
+#[derive(AirbendTable)]
+#[airbend_table(table_name = "reading", table_name = "readings")]
+struct Reading {
+    site: String,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use airbend_table::AirbendTable;
+
+#[derive(AirbendTable)]
+#[airbend_table(table_name = "reading")]
+enum Reading {
+    Site(String),
+}
+
+fn main() {}
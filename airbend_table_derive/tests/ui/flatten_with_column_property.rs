@@ -0,0 +1,17 @@
+use airbend_table::{AirbendColumns, AirbendTable};
+
+#[derive(AirbendColumns)]
+struct Site {
+    site_code: String,
+}
+
+#[derive(AirbendTable)]
+#[airbend_table(table_name = "reading")]
+struct Reading {
+    #[airbend_col(flatten, key)]
+    site: Site,
+    #[airbend_col(name = "meta", flatten)]
+    meta: Site,
+}
+
+fn main() {}
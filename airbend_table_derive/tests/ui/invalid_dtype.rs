@@ -0,0 +1,12 @@
+use airbend_table::AirbendTable;
+
+#[derive(AirbendTable)]
+#[airbend_table(table_name = "reading")]
+struct Reading {
+    #[airbend_col(dtype = "VARCHAR(")]
+    site: String,
+    #[airbend_col(dtype = "FLOAT128")]
+    value: f64,
+}
+
+fn main() {}
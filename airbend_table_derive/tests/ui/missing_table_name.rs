@@ -0,0 +1,8 @@
+use airbend_table::AirbendTable;
+
+#[derive(AirbendTable)]
+struct Reading {
+    site: String,
+}
+
+fn main() {}
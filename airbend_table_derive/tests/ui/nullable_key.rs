@@ -0,0 +1,12 @@
+use airbend_table::AirbendTable;
+
+#[derive(AirbendTable)]
+#[airbend_table(table_name = "reading")]
+struct Reading {
+    #[airbend_col(key)]
+    site_code: String,
+    #[airbend_col(key)]
+    species_code: Option<String>,
+}
+
+fn main() {}
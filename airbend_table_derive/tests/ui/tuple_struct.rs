@@ -0,0 +1,7 @@
+use airbend_table::AirbendTable;
+
+#[derive(AirbendTable)]
+#[airbend_table(table_name = "reading")]
+struct Reading(String, f64);
+
+fn main() {}